@@ -1,47 +1,222 @@
 use std::collections::HashMap;
+use std::env;
 use std::io::{self, BufRead, Read};
+use std::process;
+
+#[derive(Debug, PartialEq)]
+struct MissingValue {
+    line_number: usize,
+    line: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct Calibration {
+    sum: i32,
+    missing: Vec<MissingValue>,
+}
+
+impl Calibration {
+    fn strict(&self) -> Result<i32, String> {
+        match self.missing.first() {
+            None => Ok(self.sum),
+            Some(first) => Err(format!(
+                "{} line(s) without a calibration value, first at line {}: {:?}",
+                self.missing.len(),
+                first.line_number,
+                first.line
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Mode {
+    Words,
+    Roman,
+    Hex,
+}
+
+impl Mode {
+    fn from_name(name: &str) -> Result<Mode, String> {
+        match name {
+            "words" => Ok(Mode::Words),
+            "roman" => Ok(Mode::Roman),
+            "hex" => Ok(Mode::Hex),
+            _ => Err(format!("unknown mode: {}", name)),
+        }
+    }
+}
+
+struct Matcher {
+    tokens: Vec<(String, i32)>,
+    radix: i32,
+}
+
+impl Matcher {
+    fn new(modes: &[Mode]) -> Matcher {
+        let mut tokens = HashMap::new();
+        let mut radix = 10;
+        for mode in modes {
+            match mode {
+                Mode::Words => tokens.extend(build_string_map()),
+                Mode::Roman => tokens.extend(build_roman_map()),
+                Mode::Hex => {
+                    tokens.extend(build_hex_map());
+                    radix = 16;
+                }
+            }
+        }
+        // Longest tokens first, so "IV" wins over "I" at the same position.
+        let mut tokens: Vec<(String, i32)> = tokens.into_iter().collect();
+        tokens.sort_by_key(|(key, _)| std::cmp::Reverse(key.len()));
+        Matcher { tokens, radix }
+    }
+
+    fn words() -> Matcher {
+        Matcher::new(&[Mode::Words])
+    }
+
+    fn token_starting(&self, rest: &[u8]) -> Option<i32> {
+        let &first = rest.first()?;
+        if first.is_ascii_digit() {
+            return Some((first - b'0') as i32);
+        }
+        self.longest(|key| key.first() == Some(&first) && rest.starts_with(key))
+    }
+
+    fn token_ending(&self, prefix: &[u8]) -> Option<i32> {
+        let &last = prefix.last()?;
+        if last.is_ascii_digit() {
+            return Some((last - b'0') as i32);
+        }
+        self.longest(|key| key.last() == Some(&last) && prefix.ends_with(key))
+    }
+
+    // The char-based counterpart of token_starting and token_ending: a
+    // decimal digit `c` wins outright, otherwise the longest token that
+    // `matches`.
+    fn char_token<F: Fn(&str) -> bool>(&self, c: char, matches: F) -> Option<i32> {
+        match c.to_digit(10) {
+            Some(digit) => Some(digit as i32),
+            None => self
+                .tokens
+                .iter()
+                .find(|(key, _)| matches(key))
+                .map(|&(_, value)| value),
+        }
+    }
+
+    fn longest<F: Fn(&[u8]) -> bool>(&self, matches: F) -> Option<i32> {
+        self.tokens
+            .iter()
+            .find(|(key, _)| matches(key.as_bytes()))
+            .map(|&(_, value)| value)
+    }
+}
 
 fn main() {
-    let result = process_input(io::stdin().lock());
-    println!("{}", result);
+    let mut strict = false;
+    let mut matcher = Matcher::words();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strict" => strict = true,
+            "--mode" => {
+                let names = args.next().unwrap_or_else(|| {
+                    eprintln!("error: --mode requires a value");
+                    process::exit(2);
+                });
+                let modes = names
+                    .split(',')
+                    .map(Mode::from_name)
+                    .collect::<Result<Vec<Mode>, String>>()
+                    .unwrap_or_else(|err| {
+                        eprintln!("error: {}", err);
+                        process::exit(2);
+                    });
+                matcher = Matcher::new(&modes);
+            }
+            _ => {
+                eprintln!("error: unknown argument: {}", arg);
+                process::exit(2);
+            }
+        }
+    }
+    let calibration = process_input(io::stdin().lock(), &matcher);
+    for missing in &calibration.missing {
+        eprintln!(
+            "line {}: no calibration value: {:?}",
+            missing.line_number, missing.line
+        );
+    }
+    if strict {
+        if let Err(err) = calibration.strict() {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+    println!(
+        "sum: {}, missing: {}",
+        calibration.sum,
+        calibration.missing.len()
+    );
 }
 
-fn process_input<R: Read>(reader: R) -> i32 {
-    let string_map = build_string_map();
+fn process_input<R: Read>(reader: R, matcher: &Matcher) -> Calibration {
     let buffered = io::BufReader::new(reader);
-    let mut result = 0;
-    for line_result in buffered.lines() {
+    let mut sum = 0;
+    let mut missing = Vec::new();
+    for (index, line_result) in buffered.lines().enumerate() {
         let line = line_result.unwrap();
-        if let Some(line_value) = number_from_string(&string_map, &line) {
-            result += line_value;
+        if line.trim().is_empty() {
+            continue;
         }
+        match line_value(matcher, &line) {
+            Some(line_value) => sum += line_value,
+            None => missing.push(MissingValue {
+                line_number: index + 1,
+                line,
+            }),
+        }
+    }
+    Calibration { sum, missing }
+}
+
+fn line_value(matcher: &Matcher, input: &str) -> Option<i32> {
+    if input.is_ascii() {
+        number_from_bytes(matcher, input.as_bytes())
+    } else {
+        number_from_string(matcher, input)
     }
-    result
 }
 
-fn number_from_string(string_map: &HashMap<String, i32>, input: &str) -> Option<i32> {
+fn number_from_bytes(matcher: &Matcher, input: &[u8]) -> Option<i32> {
+    let first_digit = (0..input.len()).find_map(|start| matcher.token_starting(&input[start..]))?;
+    let last_digit = (1..=input.len())
+        .rev()
+        .find_map(|end| matcher.token_ending(&input[..end]))?;
+    Some(first_digit * matcher.radix + last_digit)
+}
+
+// The fallback for lines that aren't ASCII, walking the line by char. At
+// each char it looks ahead for the first token starting there, until one is
+// found, and checks the text read so far for a token ending there. Like
+// number_from_bytes, the first value is the longest token starting earliest
+// and the last is the longest token ending latest.
+fn number_from_string(matcher: &Matcher, input: &str) -> Option<i32> {
     let mut current_str = String::new();
     let mut first_digit = None;
     let mut last_digit = 0;
-    for c in input.chars() {
+    for (start, c) in input.char_indices() {
         current_str.push(c);
-
-        if let Some(digit) = c.to_digit(10) {
-            last_digit = digit as i32;
-            if first_digit.is_none() {
-                first_digit = Some(last_digit);
-            }
-        } else if let Some((_key, &value)) = string_map
-            .iter()
-            .find(|&(key, _)| current_str.ends_with(key))
-        {
-            last_digit = value;
-            if first_digit.is_none() {
-                first_digit = Some(last_digit);
-            }
+        if first_digit.is_none() {
+            first_digit = matcher.char_token(c, |key| input[start..].starts_with(key));
+        }
+        if let Some(digit) = matcher.char_token(c, |key| current_str.ends_with(key)) {
+            last_digit = digit;
         }
     }
-    first_digit.map(|fd| fd * 10 + last_digit)
+    first_digit.map(|fd| fd * matcher.radix + last_digit)
 }
 
 fn build_string_map() -> HashMap<String, i32> {
@@ -58,82 +233,224 @@ fn build_string_map() -> HashMap<String, i32> {
     map
 }
 
+fn build_roman_map() -> HashMap<String, i32> {
+    let mut map = HashMap::new();
+    map.insert("I".to_string(), 1);
+    map.insert("II".to_string(), 2);
+    map.insert("III".to_string(), 3);
+    map.insert("IV".to_string(), 4);
+    map.insert("V".to_string(), 5);
+    map.insert("VI".to_string(), 6);
+    map.insert("VII".to_string(), 7);
+    map.insert("VIII".to_string(), 8);
+    map.insert("IX".to_string(), 9);
+    map
+}
+
+fn build_hex_map() -> HashMap<String, i32> {
+    let mut map = HashMap::new();
+    for (offset, (lower, upper)) in ('a'..='f').zip('A'..='F').enumerate() {
+        map.insert(lower.to_string(), 10 + offset as i32);
+        map.insert(upper.to_string(), 10 + offset as i32);
+    }
+    map
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_number_from_string_simple() {
-        assert_eq!(number_from_string(&build_string_map(), "12"), Some(12));
+        assert_eq!(number_from_string(&Matcher::words(), "12"), Some(12));
     }
 
     #[test]
     fn test_number_from_string_1_character() {
-        assert_eq!(number_from_string(&build_string_map(), "2x3"), Some(23));
+        assert_eq!(number_from_string(&Matcher::words(), "2x3"), Some(23));
     }
 
     #[test]
     fn test_number_from_string_alpha_numbers() {
+        assert_eq!(number_from_string(&Matcher::words(), "twothree"), Some(23));
+        assert_eq!(number_from_string(&Matcher::words(), "twoxthree"), Some(23));
+        assert_eq!(number_from_string(&Matcher::words(), "two1nine"), Some(29));
         assert_eq!(
-            number_from_string(&build_string_map(), "twothree"),
-            Some(23)
-        );
-        assert_eq!(
-            number_from_string(&build_string_map(), "twoxthree"),
-            Some(23)
-        );
-        assert_eq!(
-            number_from_string(&build_string_map(), "two1nine"),
-            Some(29)
-        );
-        assert_eq!(
-            number_from_string(&build_string_map(), "eightwothree"),
+            number_from_string(&Matcher::words(), "eightwothree"),
             Some(83)
         );
         assert_eq!(
-            number_from_string(&build_string_map(), "abcone2threexyz"),
+            number_from_string(&Matcher::words(), "abcone2threexyz"),
             Some(13)
         );
         assert_eq!(
-            number_from_string(&build_string_map(), "xtwone3four"),
+            number_from_string(&Matcher::words(), "xtwone3four"),
             Some(24)
         );
         assert_eq!(
-            number_from_string(&build_string_map(), "4nineeightseven2"),
+            number_from_string(&Matcher::words(), "4nineeightseven2"),
             Some(42)
         );
         assert_eq!(
-            number_from_string(&build_string_map(), "zoneight234"),
+            number_from_string(&Matcher::words(), "zoneight234"),
             Some(14)
         );
         assert_eq!(
-            number_from_string(&build_string_map(), "7pqrstsixteen"),
+            number_from_string(&Matcher::words(), "7pqrstsixteen"),
             Some(76)
         );
     }
 
     #[test]
     fn test_number_from_string_tricky() {
-        assert_eq!(number_from_string(&build_string_map(), "twone"), Some(21));
+        assert_eq!(number_from_string(&Matcher::words(), "twone"), Some(21));
     }
 
     #[test]
     fn test_number_from_string_advent_input() {
-        assert_eq!(number_from_string(&build_string_map(), "1abc2"), Some(12));
+        assert_eq!(number_from_string(&Matcher::words(), "1abc2"), Some(12));
         assert_eq!(
-            number_from_string(&build_string_map(), "pqr3stu8vwx"),
+            number_from_string(&Matcher::words(), "pqr3stu8vwx"),
             Some(38)
         );
         assert_eq!(
-            number_from_string(&build_string_map(), "a1b2c3d4e5f"),
+            number_from_string(&Matcher::words(), "a1b2c3d4e5f"),
             Some(15)
         );
         assert_eq!(
-            number_from_string(&build_string_map(), "treb7uchet"),
+            number_from_string(&Matcher::words(), "treb7uchet"),
             Some(77)
         );
     }
 
+    #[test]
+    fn test_number_from_bytes_matches_number_from_string() {
+        let matcher = Matcher::words();
+        for line in [
+            "12",
+            "2x3",
+            "twone",
+            "eightwothree",
+            "xtwone3four",
+            "zoneight234",
+            "7pqrstsixteen",
+            "oneight",
+            "nothing",
+            "",
+        ] {
+            assert_eq!(
+                number_from_string(&matcher, line),
+                number_from_bytes(&matcher, line.as_bytes()),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_line_value_non_ascii_fallback() {
+        let matcher = Matcher::words();
+        assert_eq!(line_value(&matcher, "ünë7twö"), Some(77));
+        assert_eq!(line_value(&matcher, "€one€2"), Some(12));
+    }
+
+    #[test]
+    fn test_number_from_string_roman() {
+        let matcher = Matcher::new(&[Mode::Roman]);
+        assert_eq!(number_from_string(&matcher, "I"), Some(11));
+        assert_eq!(number_from_string(&matcher, "xIxVx"), Some(15));
+        assert_eq!(number_from_string(&matcher, "IX"), Some(99));
+        assert_eq!(number_from_string(&matcher, "3VIII"), Some(38));
+        assert_eq!(number_from_string(&matcher, "nothing"), None);
+    }
+
+    #[test]
+    fn test_number_from_string_roman_longest_match() {
+        let matcher = Matcher::new(&[Mode::Roman]);
+        assert_eq!(number_from_string(&matcher, "IV"), Some(44));
+        assert_eq!(number_from_string(&matcher, "VIII"), Some(88));
+        assert_eq!(number_from_string(&matcher, "VIV"), Some(64));
+        assert_eq!(number_from_string(&matcher, "I V"), Some(15));
+        assert_eq!(number_from_string(&matcher, "VII.II"), Some(72));
+    }
+
+    #[test]
+    fn test_number_from_bytes_roman_matches_number_from_string() {
+        let matcher = Matcher::new(&[Mode::Roman]);
+        for line in ["IV", "VIII", "VIV", "I V", "XIX", "IIII", "VI", "a1VIIb"] {
+            assert_eq!(
+                number_from_string(&matcher, line),
+                number_from_bytes(&matcher, line.as_bytes()),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_number_from_string_hex() {
+        let matcher = Matcher::new(&[Mode::Hex]);
+        assert_eq!(number_from_string(&matcher, "0x1F"), Some(0x0F));
+        assert_eq!(number_from_string(&matcher, "zz9"), Some(0x99));
+        assert_eq!(number_from_string(&matcher, "Ab"), Some(0xAB));
+        assert_eq!(number_from_bytes(&matcher, b"q3..e"), Some(0x3E));
+    }
+
+    #[test]
+    fn test_number_from_string_combined_modes() {
+        let matcher = Matcher::new(&[Mode::Words, Mode::Roman]);
+        assert_eq!(number_from_string(&matcher, "IVxfive"), Some(45));
+        assert_eq!(number_from_string(&matcher, "twoIX"), Some(29));
+    }
+
+    #[test]
+    fn test_process_input_roman() {
+        let input = r#"
+aIVb
+VII.II
+"#;
+
+        let result = process_input(input.as_bytes(), &Matcher::new(&[Mode::Roman])).sum;
+        assert_eq!(44 + 72, result);
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_puzzle_input_scaled() {
+        use std::time::Instant;
+
+        let matcher = Matcher::words();
+        let input = include_str!("../puzzle.input");
+        let lines: Vec<&str> = input.lines().collect();
+        let repeat = 10_000;
+
+        let start = Instant::now();
+        let mut char_sum = 0;
+        for _ in 0..repeat {
+            for line in &lines {
+                char_sum += number_from_string(&matcher, line).unwrap_or(0);
+            }
+        }
+        let char_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        let mut byte_sum = 0;
+        for _ in 0..repeat {
+            for line in &lines {
+                byte_sum += number_from_bytes(&matcher, line.as_bytes()).unwrap_or(0);
+            }
+        }
+        let byte_elapsed = start.elapsed();
+
+        assert_eq!(char_sum, byte_sum);
+        println!(
+            "{} lines: chars {:?}, bytes {:?}",
+            lines.len() * repeat,
+            char_elapsed,
+            byte_elapsed
+        );
+    }
+
     #[test]
     fn test_process_input_advent_input_1() {
         let input = r#"
@@ -143,7 +460,7 @@ a1b2c3d4e5f
 treb7uchet
 "#;
 
-        let result = process_input(input.as_bytes());
+        let result = process_input(input.as_bytes(), &Matcher::words()).sum;
         assert_eq!(142, result);
     }
 
@@ -159,7 +476,47 @@ zoneight234
 7pqrstsixteen
 "#;
 
-        let result = process_input(input.as_bytes());
+        let result = process_input(input.as_bytes(), &Matcher::words()).sum;
         assert_eq!(281, result);
     }
+
+    #[test]
+    fn test_process_input_reports_missing_lines() {
+        let input = r#"
+1abc2
+nothing here
+
+xyz
+treb7uchet
+"#;
+
+        let calibration = process_input(input.as_bytes(), &Matcher::words());
+        assert_eq!(89, calibration.sum);
+        assert_eq!(
+            vec![
+                MissingValue {
+                    line_number: 3,
+                    line: "nothing here".to_string(),
+                },
+                MissingValue {
+                    line_number: 5,
+                    line: "xyz".to_string(),
+                },
+            ],
+            calibration.missing
+        );
+        assert!(calibration.strict().is_err());
+    }
+
+    #[test]
+    fn test_process_input_strict_ok() {
+        let input = r#"
+two1nine
+eightwothree
+"#;
+
+        let calibration = process_input(input.as_bytes(), &Matcher::words());
+        assert!(calibration.missing.is_empty());
+        assert_eq!(Ok(112), calibration.strict());
+    }
 }
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::{Color, Game};
+
+// Counts are taken over the reveals that mention the color; a reveal that
+// doesn't show a color doesn't count as a 0 for it. Queries use the same
+// rule for min(), max() and sum().
+#[derive(Debug, PartialEq)]
+pub struct ColorStats {
+    pub color: Color,
+    pub reveals: usize,
+    pub min: u32,
+    pub max: u32,
+    pub sum: u64,
+    pub histogram: BTreeMap<u32, usize>,
+}
+
+impl ColorStats {
+    pub fn mean(&self) -> f64 {
+        self.sum as f64 / self.reveals as f64
+    }
+}
+
+pub fn write_rows<W: Write>(games: &[Game], separator: char, out: &mut W) -> io::Result<()> {
+    writeln!(out, "game{sep}reveal{sep}color{sep}count", sep = separator)?;
+    for game in games {
+        for (reveal_index, reveal) in game.reveals.iter().enumerate() {
+            for (color, count) in &reveal.cubes {
+                writeln!(
+                    out,
+                    "{}{sep}{}{sep}{}{sep}{}",
+                    game.id,
+                    reveal_index + 1,
+                    escape(&color.0, separator),
+                    count,
+                    sep = separator
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn summarize(games: &[Game]) -> Vec<ColorStats> {
+    let mut stats: BTreeMap<&Color, ColorStats> = BTreeMap::new();
+    for reveal in games.iter().flat_map(|game| &game.reveals) {
+        for (color, count) in &reveal.cubes {
+            let entry = stats.entry(color).or_insert_with(|| ColorStats {
+                color: color.clone(),
+                reveals: 0,
+                min: *count,
+                max: *count,
+                sum: 0,
+                histogram: BTreeMap::new(),
+            });
+            entry.reveals += 1;
+            entry.min = entry.min.min(*count);
+            entry.max = entry.max.max(*count);
+            entry.sum += *count as u64;
+            *entry.histogram.entry(*count).or_insert(0) += 1;
+        }
+    }
+    stats.into_values().collect()
+}
+
+pub fn write_summary<W: Write>(games: &[Game], out: &mut W) -> io::Result<()> {
+    let stats = summarize(games);
+    writeln!(out, "games: {}", games.len())?;
+    writeln!(
+        out,
+        "reveals: {}",
+        games.iter().map(|game| game.reveals.len()).sum::<usize>()
+    )?;
+    for stat in &stats {
+        writeln!(
+            out,
+            "{}: reveals: {}, min: {}, mean: {:.2}, max: {}",
+            stat.color,
+            stat.reveals,
+            stat.min,
+            stat.mean(),
+            stat.max
+        )?;
+    }
+    for stat in &stats {
+        writeln!(out)?;
+        writeln!(out, "{} histogram:", stat.color)?;
+        let widest = stat.histogram.values().copied().max().unwrap_or(0);
+        for (count, &frequency) in &stat.histogram {
+            // Scale the longest bar to 40 columns, keeping at least one mark.
+            let bar = (frequency * 40).div_ceil(widest.max(1));
+            writeln!(out, "{:>4} | {} {}", count, "#".repeat(bar), frequency)?;
+        }
+    }
+    Ok(())
+}
+
+fn escape(field: &str, separator: char) -> String {
+    if field.contains(separator) || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_from_line;
+    use crate::tests::sample_games;
+
+    #[test]
+    fn test_write_rows_csv() {
+        let games = vec![game_from_line("Game 1: 3 blue, 4 red; 2 green")];
+        let mut out = Vec::new();
+        write_rows(&games, ',', &mut out).unwrap();
+        assert_eq!(
+            "game,reveal,color,count\n1,1,blue,3\n1,1,red,4\n1,2,green,2\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_rows_tsv_and_escaping() {
+        let games = vec![game_from_line("Game 9: 1 a,b; 2 c\"d")];
+        let mut out = Vec::new();
+        write_rows(&games, '\t', &mut out).unwrap();
+        assert_eq!(
+            "game\treveal\tcolor\tcount\n9\t1\ta,b\t1\n9\t2\t\"c\"\"d\"\t2\n",
+            String::from_utf8(out).unwrap()
+        );
+        let mut out = Vec::new();
+        write_rows(&games, ',', &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("9,1,\"a,b\",1\n"));
+    }
+
+    #[test]
+    fn test_write_rows_one_row_per_reveal_color() {
+        let games = sample_games();
+        let mut out = Vec::new();
+        write_rows(&games, ',', &mut out).unwrap();
+        let cubes: usize = games
+            .iter()
+            .flat_map(|game| &game.reveals)
+            .map(|reveal| reveal.cubes.len())
+            .sum();
+        assert_eq!(cubes + 1, String::from_utf8(out).unwrap().lines().count());
+    }
+
+    #[test]
+    fn test_summarize_sample() {
+        let stats = summarize(&sample_games());
+        assert_eq!(
+            vec!["blue", "green", "red"],
+            stats
+                .iter()
+                .map(|stat| stat.color.0.as_str())
+                .collect::<Vec<&str>>()
+        );
+        let red = &stats[2];
+        assert_eq!(11, red.reveals);
+        assert_eq!(1, red.min);
+        assert_eq!(20, red.max);
+        assert_eq!(61, red.sum);
+        assert!((red.mean() - 61.0 / 11.0).abs() < 1e-9);
+        assert_eq!(Some(&4), red.histogram.get(&1));
+        assert_eq!(red.reveals, red.histogram.values().sum::<usize>());
+    }
+}
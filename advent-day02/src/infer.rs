@@ -0,0 +1,289 @@
+use crate::rng::Rng;
+use crate::simulate::draw_reveal;
+use crate::{Color, Game, Reveal};
+
+// Each reveal is modelled as drawing its cubes from the bag without
+// replacement, with the cubes put back before the next reveal. The
+// likelihood of a reveal is then multivariate hypergeometric:
+//
+//     prod_c C(n_c, x_c) / C(N, k)
+//
+// which only couples the colors through the bag total N, so the search
+// below maximises over each total with a small knapsack instead of trying
+// every combination of counts.
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Inference {
+    pub max_extra: u32,
+    pub simulations: usize,
+    pub confidence: f64,
+}
+
+impl Default for Inference {
+    fn default() -> Inference {
+        Inference {
+            max_extra: 20,
+            simulations: 200,
+            confidence: 0.95,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Estimate {
+    pub bag: Reveal,
+    pub log_likelihood: f64,
+    pub at_bound: bool,
+    pub intervals: Vec<(Color, u32, u32)>,
+}
+
+pub fn estimate_game(game: &Game, inference: &Inference, rng: &mut Rng) -> Estimate {
+    estimate_reveals(&game.reveals, inference, rng)
+}
+
+pub fn estimate_games(games: &[Game], inference: &Inference, rng: &mut Rng) -> Estimate {
+    let reveals: Vec<Reveal> = games
+        .iter()
+        .flat_map(|game| game.reveals.iter().cloned())
+        .collect();
+    estimate_reveals(&reveals, inference, rng)
+}
+
+fn estimate_reveals(reveals: &[Reveal], inference: &Inference, rng: &mut Rng) -> Estimate {
+    let colors = observed_colors(reveals);
+    let (bag, log_likelihood, at_bound) = mle_bag(&colors, reveals, inference.max_extra);
+
+    let sizes: Vec<u32> = reveals.iter().map(Reveal::total).collect();
+    let mut samples: Vec<Vec<u32>> = vec![Vec::new(); colors.len()];
+    for _ in 0..inference.simulations {
+        let simulated: Vec<Reveal> = sizes
+            .iter()
+            .map(|&size| draw_reveal(&bag, size, rng))
+            .collect();
+        let (resampled, _, _) = mle_bag(&colors, &simulated, inference.max_extra);
+        for (index, (_, count)) in resampled.cubes.iter().enumerate() {
+            samples[index].push(*count);
+        }
+    }
+
+    let tail = (1.0 - inference.confidence) / 2.0;
+    let intervals = colors
+        .iter()
+        .zip(samples.iter_mut())
+        .zip(bag.cubes.iter())
+        .map(|((color, samples), &(_, mle))| {
+            if samples.is_empty() {
+                return (color.clone(), mle, mle);
+            }
+            samples.sort_unstable();
+            let last = (samples.len() - 1) as f64;
+            // The bag can't hold fewer cubes than were actually seen, even
+            // if some simulated games happened to show fewer.
+            let low = samples[(tail * last).floor() as usize].max(observed_max(reveals, color));
+            let high = samples[((1.0 - tail) * last).ceil() as usize];
+            (color.clone(), low, high)
+        })
+        .collect();
+
+    Estimate {
+        bag,
+        log_likelihood,
+        at_bound,
+        intervals,
+    }
+}
+
+// Returns the most likely bag over `colors`, its log-likelihood and whether
+// any count ended up on the search limit (meaning a larger limit could find
+// a better bag). Each color is searched from its observed maximum up to
+// `max_extra` more than that; ties go to the smaller bag.
+pub fn mle_bag(colors: &[Color], reveals: &[Reveal], max_extra: u32) -> (Reveal, f64, bool) {
+    let lows: Vec<u32> = colors
+        .iter()
+        .map(|color| observed_max(reveals, color))
+        .collect();
+    let low_total: u32 = lows.iter().sum();
+    let ln_factorials = ln_factorials((low_total + max_extra * colors.len() as u32) as usize);
+    let ln_choose = |n: u32, k: u32| {
+        ln_factorials[n as usize] - ln_factorials[k as usize] - ln_factorials[(n - k) as usize]
+    };
+
+    // best[t]: best summed color term for a bag of low_total + t cubes.
+    let mut best = vec![0.0];
+    let mut choices: Vec<Vec<u32>> = Vec::new();
+    for (color, &low) in colors.iter().zip(&lows) {
+        let terms: Vec<f64> = (0..=max_extra)
+            .map(|extra| {
+                reveals
+                    .iter()
+                    .map(|reveal| ln_choose(low + extra, reveal.get(color).unwrap_or(0)))
+                    .sum()
+            })
+            .collect();
+        let mut next = vec![f64::NEG_INFINITY; best.len() + max_extra as usize];
+        let mut choice = vec![0; next.len()];
+        for (t, &previous) in best.iter().enumerate() {
+            for (extra, &term) in terms.iter().enumerate() {
+                if previous + term > next[t + extra] {
+                    next[t + extra] = previous + term;
+                    choice[t + extra] = extra as u32;
+                }
+            }
+        }
+        best = next;
+        choices.push(choice);
+    }
+
+    let sizes: Vec<u32> = reveals.iter().map(Reveal::total).collect();
+    let mut best_total = 0;
+    let mut best_score = f64::NEG_INFINITY;
+    for (t, &color_terms) in best.iter().enumerate() {
+        let total = low_total + t as u32;
+        let score = color_terms - sizes.iter().map(|&k| ln_choose(total, k)).sum::<f64>();
+        if score > best_score + 1e-9 {
+            best_score = score;
+            best_total = t;
+        }
+    }
+
+    let mut counts = vec![0; colors.len()];
+    let mut t = best_total;
+    for index in (0..colors.len()).rev() {
+        let extra = choices[index][t];
+        counts[index] = lows[index] + extra;
+        t -= extra as usize;
+    }
+    let at_bound = max_extra > 0 && counts.iter().zip(&lows).any(|(c, l)| *c == l + max_extra);
+    let bag = Reveal {
+        cubes: colors.iter().cloned().zip(counts).collect(),
+    };
+    (bag, best_score, at_bound)
+}
+
+fn observed_colors(reveals: &[Reveal]) -> Vec<Color> {
+    let mut colors: Vec<Color> = Vec::new();
+    for reveal in reveals {
+        for (color, _) in &reveal.cubes {
+            if !colors.contains(color) {
+                colors.push(color.clone());
+            }
+        }
+    }
+    colors
+}
+
+fn observed_max(reveals: &[Reveal], color: &Color) -> u32 {
+    reveals
+        .iter()
+        .filter_map(|reveal| reveal.get(color))
+        .max()
+        .unwrap_or(0)
+}
+
+fn ln_factorials(max: usize) -> Vec<f64> {
+    let mut table = vec![0.0; max + 1];
+    for n in 1..=max {
+        table[n] = table[n - 1] + (n as f64).ln();
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bag_from_spec, game_from_line};
+
+    #[test]
+    fn test_mle_single_reveal_is_the_reveal() {
+        // Drawing every cube is the only way to be certain of the draw.
+        let game = game_from_line("Game 1: 3 blue, 4 red");
+        let colors = [Color::new("blue"), Color::new("red")];
+        let (bag, log_likelihood, at_bound) = mle_bag(&colors, &game.reveals, 20);
+        assert_eq!(bag_from_spec("blue=3,red=4").unwrap(), bag);
+        assert!(log_likelihood.abs() < 1e-9);
+        assert!(!at_bound);
+    }
+
+    #[test]
+    fn test_mle_matches_brute_force() {
+        let game = game_from_line("Game 1: 1 blue, 1 red; 1 red, 1 green; 2 blue; 1 green");
+        let colors = [Color::new("blue"), Color::new("red"), Color::new("green")];
+        let max_extra = 6;
+        let (bag, log_likelihood, _) = mle_bag(&colors, &game.reveals, max_extra);
+
+        let ln_factorials = ln_factorials(64);
+        let ln_choose = |n: u32, k: u32| {
+            ln_factorials[n as usize] - ln_factorials[k as usize] - ln_factorials[(n - k) as usize]
+        };
+        let mut best = (f64::NEG_INFINITY, vec![]);
+        for blue in 2..=2 + max_extra {
+            for red in 1..=1 + max_extra {
+                for green in 1..=1 + max_extra {
+                    let counts = [blue, red, green];
+                    let total = blue + red + green;
+                    let score: f64 = game
+                        .reveals
+                        .iter()
+                        .map(|reveal| {
+                            let drawn: u32 = reveal.cubes.iter().map(|(_, c)| c).sum();
+                            colors
+                                .iter()
+                                .zip(counts)
+                                .map(|(color, n)| ln_choose(n, reveal.get(color).unwrap_or(0)))
+                                .sum::<f64>()
+                                - ln_choose(total, drawn)
+                        })
+                        .sum();
+                    if score > best.0 + 1e-9 {
+                        best = (score, counts.to_vec());
+                    }
+                }
+            }
+        }
+        assert!((best.0 - log_likelihood).abs() < 1e-9);
+        assert_eq!(
+            best.1,
+            bag.cubes.iter().map(|(_, c)| *c).collect::<Vec<u32>>()
+        );
+    }
+
+    #[test]
+    fn test_mle_merges_repeated_colors() {
+        let game = game_from_line("Game 1: 3 red, 2 red");
+        let colors = [Color::new("red")];
+        let (bag, _, _) = mle_bag(&colors, &game.reveals, 20);
+        assert_eq!(bag_from_spec("red=5").unwrap(), bag);
+
+        let mut rng = Rng::new(2023);
+        let estimate = estimate_game(&game, &Inference::default(), &mut rng);
+        assert_eq!(bag, estimate.bag);
+        assert_eq!(vec![(Color::new("red"), 5, 5)], estimate.intervals);
+    }
+
+    #[test]
+    fn test_estimate_game_interval_contains_mle() {
+        let game = game_from_line("Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green");
+        let mut rng = Rng::new(2023);
+        let estimate = estimate_game(&game, &Inference::default(), &mut rng);
+        assert_eq!(3, estimate.intervals.len());
+        for ((color, low, high), (bag_color, mle)) in
+            estimate.intervals.iter().zip(&estimate.bag.cubes)
+        {
+            assert_eq!(color, bag_color);
+            assert!(
+                low <= mle && mle <= high,
+                "{} {}..{} {}",
+                color,
+                low,
+                high,
+                mle
+            );
+        }
+
+        let mut again = Rng::new(2023);
+        assert_eq!(
+            estimate,
+            estimate_game(&game, &Inference::default(), &mut again)
+        );
+    }
+}
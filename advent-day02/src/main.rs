@@ -1,16 +1,76 @@
-use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
 use std::io::{self, BufRead, Read};
+use std::process;
 
-#[derive(Hash, Eq, PartialEq, Debug, Copy, Clone)]
-enum Color {
-    Red,
-    Green,
-    Blue,
+mod export;
+mod infer;
+mod query;
+mod rng;
+mod simulate;
+
+use infer::Inference;
+use query::Query;
+use rng::Rng;
+use simulate::RevealCount;
+
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Debug, Clone)]
+struct Color(String);
+
+impl Color {
+    fn new(name: &str) -> Color {
+        Color(name.to_string())
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 struct Reveal {
-    cubes: HashMap<Color, u32>,
+    cubes: Vec<(Color, u32)>,
+}
+
+impl Reveal {
+    fn get(&self, color: &Color) -> Option<u32> {
+        self.cubes
+            .iter()
+            .find(|(c, _)| c == color)
+            .map(|&(_, count)| count)
+    }
+
+    fn total(&self) -> u32 {
+        self.cubes.iter().map(|(_, count)| count).sum()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Reveal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, (color, count)) in self.cubes.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}", count, color)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Game {}: ", self.id)?;
+        for (index, reveal) in self.reveals.iter().enumerate() {
+            if index > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", reveal)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -20,58 +80,420 @@ struct Game {
     reveals: Vec<Reveal>,
 }
 
+impl Game {
+    fn new(id: u32, reveals: Vec<Reveal>) -> Game {
+        let mut game = Game {
+            id,
+            reveals,
+            power: 0,
+        };
+        game.power = game.power_with(&PowerMode::required());
+        game
+    }
+
+    fn minimal_bag(&self) -> Reveal {
+        let mut cubes: Vec<(Color, u32)> = Vec::new();
+        for reveal in &self.reveals {
+            for (color, count) in &reveal.cubes {
+                match cubes.iter_mut().find(|(c, _)| c == color) {
+                    Some((_, max_count)) => *max_count = (*max_count).max(*count),
+                    None => cubes.push((color.clone(), *count)),
+                }
+            }
+        }
+        Reveal { cubes }
+    }
+
+    fn power_with(&self, mode: &PowerMode) -> u32 {
+        let minimal_bag = self.minimal_bag();
+        if let PowerMode::Required(colors) = mode {
+            if colors.iter().any(|color| minimal_bag.get(color).is_none()) {
+                return 0;
+            }
+        }
+        minimal_bag.cubes.iter().map(|(_, count)| count).product()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum PowerMode {
+    Observed,
+    Required(Vec<Color>),
+}
+
+impl PowerMode {
+    fn required() -> PowerMode {
+        PowerMode::Required(vec![
+            Color::new("red"),
+            Color::new("green"),
+            Color::new("blue"),
+        ])
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Violation {
+    reveal_index: usize,
+    color: Color,
+    count: u32,
+    limit: Option<u32>,
+}
+
+impl Violation {
+    fn excess(&self) -> u32 {
+        self.count - self.limit.unwrap_or(0)
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.limit {
+            Some(limit) => write!(
+                f,
+                "reveal {}: {} {} exceeds {} by {}",
+                self.reveal_index + 1,
+                self.count,
+                self.color,
+                limit,
+                self.excess()
+            ),
+            None => write!(
+                f,
+                "reveal {}: {} {} not in bag",
+                self.reveal_index + 1,
+                self.count,
+                self.color
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct GameValidation {
+    id: u32,
+    violations: Vec<Violation>,
+}
+
+impl GameValidation {
+    fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
 fn main() {
-    let (id_sum, power_sum) = process_input(io::stdin().lock());
-    println!("id_sum: {}, power_sum: {}", id_sum, power_sum);
+    let mut bags: Vec<(String, Reveal)> = Vec::new();
+    let mut report = false;
+    let mut minimal_bags = false;
+    let mut power_mode = PowerMode::required();
+    let mut infer = false;
+    let mut inference = Inference::default();
+    let mut seed = 2023;
+    let mut simulate_games = None;
+    let mut reveal_count = RevealCount::Uniform(1, 6);
+    let mut query = None;
+    let mut export = None;
+    let mut summary = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bag" => {
+                let spec = expect_value(&arg, args.next());
+                bags.push((spec.clone(), or_exit(bag_from_spec(&spec))));
+            }
+            "--bag-file" => {
+                let path = expect_value(&arg, args.next());
+                let contents =
+                    or_exit(fs::read_to_string(&path).map_err(|err| format!("{}: {}", path, err)));
+                bags.extend(or_exit(bags_from_file(&contents)));
+            }
+            "--report" => report = true,
+            "--minimal-bags" => minimal_bags = true,
+            "--power" => {
+                power_mode = match expect_value(&arg, args.next()).as_str() {
+                    "observed" => PowerMode::Observed,
+                    "required" => PowerMode::required(),
+                    other => or_exit(Err(format!("unknown power mode: {}", other))),
+                }
+            }
+            "--required" => {
+                let colors = expect_value(&arg, args.next());
+                power_mode = PowerMode::Required(colors.split(',').map(Color::new).collect());
+            }
+            "--query" => query = Some(or_exit(Query::parse(&expect_value(&arg, args.next())))),
+            "--export" => {
+                export = match expect_value(&arg, args.next()).as_str() {
+                    "csv" => Some(','),
+                    "tsv" => Some('\t'),
+                    other => or_exit(Err(format!("unknown export format: {}", other))),
+                }
+            }
+            "--summary" => summary = true,
+            "--infer" => infer = true,
+            "--simulate" => simulate_games = Some(or_exit(parse_value(&arg, args.next()))),
+            "--reveals" => {
+                reveal_count = or_exit(RevealCount::from_spec(&expect_value(&arg, args.next())))
+            }
+            "--seed" => seed = or_exit(parse_value(&arg, args.next())),
+            "--simulations" => inference.simulations = or_exit(parse_value(&arg, args.next())),
+            "--max-extra" => inference.max_extra = or_exit(parse_value(&arg, args.next())),
+            _ => or_exit(Err(format!("unknown argument: {}", arg))),
+        }
+    }
+    if let Some(separator) = export {
+        let games = read_games(io::stdin().lock());
+        or_exit(
+            export::write_rows(&games, separator, &mut io::stdout().lock())
+                .map_err(|err| err.to_string()),
+        );
+        return;
+    }
+    if summary {
+        let games = read_games(io::stdin().lock());
+        or_exit(
+            export::write_summary(&games, &mut io::stdout().lock()).map_err(|err| err.to_string()),
+        );
+        return;
+    }
+    if let Some(query) = query {
+        let games = read_games(io::stdin().lock());
+        let ids: Vec<u32> = games
+            .iter()
+            .filter(|game| query.matches(game, &power_mode))
+            .map(|game| game.id)
+            .collect();
+        println!(
+            "ids: {}",
+            ids.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        println!("id_sum: {}", ids.iter().sum::<u32>());
+        return;
+    }
+    if let Some(games) = simulate_games {
+        let bag = bags
+            .first()
+            .map(|(_, bag)| bag.clone())
+            .unwrap_or_else(default_bag);
+        for game in or_exit(simulate::simulate(&bag, games, reveal_count, seed)) {
+            println!("{}", game);
+        }
+        return;
+    }
+    if infer {
+        let games = read_games(io::stdin().lock());
+        let mut rng = Rng::new(seed);
+        for game in &games {
+            let estimate = infer::estimate_game(game, &inference, &mut rng);
+            println!("game {}: {}", game.id, format_estimate(&estimate));
+        }
+        let estimate = infer::estimate_games(&games, &inference, &mut rng);
+        println!("all games: {}", format_estimate(&estimate));
+        return;
+    }
+    if minimal_bags {
+        let games = read_games(io::stdin().lock());
+        let mut power_sum = 0;
+        for game in &games {
+            let power = game.power_with(&power_mode);
+            println!(
+                "game {}: {}, power: {}",
+                game.id,
+                format_bag(&game.minimal_bag()),
+                power
+            );
+            power_sum += power;
+        }
+        println!("power_sum: {}", power_sum);
+        return;
+    }
+    if report {
+        if bags.is_empty() {
+            bags.push(("red=12,green=13,blue=14".to_string(), default_bag()));
+        }
+        let games = read_games(io::stdin().lock());
+        for (spec, bag) in &bags {
+            println!("bag: {}", spec);
+            let mut id_sum = 0;
+            for game in &games {
+                let validation = validate_game(bag, game);
+                if validation.is_valid() {
+                    id_sum += game.id;
+                    continue;
+                }
+                for violation in &validation.violations {
+                    println!("game {}: {}", validation.id, violation);
+                }
+            }
+            println!("id_sum: {}", id_sum);
+        }
+        return;
+    }
+    if bags.is_empty() {
+        let (id_sums, power_sum) = process_input(io::stdin().lock(), &[default_bag()], &power_mode);
+        println!("id_sum: {}, power_sum: {}", id_sums[0], power_sum);
+        return;
+    }
+    let reveals: Vec<Reveal> = bags.iter().map(|(_, bag)| bag.clone()).collect();
+    let (id_sums, power_sum) = process_input(io::stdin().lock(), &reveals, &power_mode);
+    for ((spec, _), id_sum) in bags.iter().zip(id_sums) {
+        println!("bag: {}, id_sum: {}", spec, id_sum);
+    }
+    println!("power_sum: {}", power_sum);
+}
+
+fn expect_value(flag: &str, value: Option<String>) -> String {
+    value.unwrap_or_else(|| or_exit(Err(format!("{} requires a value", flag))))
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    let value = expect_value(flag, value);
+    value
+        .parse::<T>()
+        .map_err(|err| format!("invalid value {:?} for {}: {}", value, flag, err))
+}
+
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        process::exit(2);
+    })
+}
+
+fn default_bag() -> Reveal {
+    Reveal {
+        cubes: vec![
+            (Color::new("red"), 12),
+            (Color::new("green"), 13),
+            (Color::new("blue"), 14),
+        ],
+    }
 }
 
-fn process_input<R: Read>(reader: R) -> (u32, u32) {
-    let mut max_reveals = HashMap::new();
-    max_reveals.insert(Color::Green, 13);
-    max_reveals.insert(Color::Red, 12);
-    max_reveals.insert(Color::Blue, 14);
-    let reveals = Reveal { cubes: max_reveals };
+fn bag_from_spec(spec: &str) -> Result<Reveal, String> {
+    let mut cubes = Vec::new();
+    for entry in spec.split(',') {
+        let (name, count) = entry
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| format!("invalid bag entry {:?}, expected color=count", entry))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("missing color name in bag {:?}", spec));
+        }
+        let count = count
+            .trim()
+            .parse::<u32>()
+            .map_err(|err| format!("invalid count {:?} in bag {:?}: {}", count, spec, err))?;
+        let color = Color::new(name);
+        if cubes.iter().any(|(c, _)| *c == color) {
+            return Err(format!("duplicate color {:?} in bag {:?}", name, spec));
+        }
+        cubes.push((color, count));
+    }
+    Ok(Reveal { cubes })
+}
+
+fn format_bag(bag: &Reveal) -> String {
+    let mut cubes: Vec<&(Color, u32)> = bag.cubes.iter().collect();
+    cubes.sort();
+    cubes
+        .iter()
+        .map(|(color, count)| format!("{}={}", color, count))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn format_estimate(estimate: &infer::Estimate) -> String {
+    let mut intervals: Vec<&(Color, u32, u32)> = estimate.intervals.iter().collect();
+    intervals.sort();
+    let intervals = intervals
+        .iter()
+        .map(|(color, low, high)| format!("{}={}..{}", color, low, high))
+        .collect::<Vec<String>>()
+        .join(",");
+    format!(
+        "mle: {}, log_likelihood: {:.3}, interval: {}{}",
+        format_bag(&estimate.bag),
+        estimate.log_likelihood,
+        intervals,
+        if estimate.at_bound {
+            " (at search limit)"
+        } else {
+            ""
+        }
+    )
+}
+
+fn bags_from_file(contents: &str) -> Result<Vec<(String, Reveal)>, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Ok((line.to_string(), bag_from_spec(line)?)))
+        .collect()
+}
+
+fn process_input<R: Read>(reader: R, bags: &[Reveal], power_mode: &PowerMode) -> (Vec<u32>, u32) {
+    let games = read_games(reader);
+    let id_sums = bags
+        .iter()
+        .map(|bag| {
+            games
+                .iter()
+                .filter(|game| valid_game(bag, game))
+                .map(|game| game.id)
+                .sum()
+        })
+        .collect();
+    let power_sum = games.iter().map(|game| game.power_with(power_mode)).sum();
+    (id_sums, power_sum)
+}
+
+fn read_games<R: Read>(reader: R) -> Vec<Game> {
     let buffered = io::BufReader::new(reader);
-    let mut id_result = 0;
-    let mut power_sum = 0;
+    let mut games = Vec::new();
     for line_result in buffered.lines() {
         let line = line_result.unwrap();
         if line.trim().is_empty() {
             continue;
         }
-        let game = game_from_line(&line);
-        if valid_game(&reveals, &game) {
-            id_result += game.id;
-        }
-        power_sum += game.power;
+        games.push(game_from_line(&line));
     }
-    (id_result, power_sum)
+    games
 }
 
 fn valid_game(max_reveals: &Reveal, game: &Game) -> bool {
-    if game.reveals.is_empty() {
-        return true;
-    }
-    for reveal in &game.reveals {
-        for (color, count) in &reveal.cubes {
-            if let Some(max_count) = max_reveals.cubes.get(color) {
-                if count > max_count {
-                    return false;
-                }
-            } else {
-                return false;
+    validate_game(max_reveals, game).is_valid()
+}
+
+fn validate_game(max_reveals: &Reveal, game: &Game) -> GameValidation {
+    let mut violations = Vec::new();
+    for (reveal_index, reveal) in game.reveals.iter().enumerate() {
+        for &(ref color, count) in &reveal.cubes {
+            let limit = max_reveals.get(color);
+            if limit.is_none_or(|limit| count > limit) {
+                violations.push(Violation {
+                    reveal_index,
+                    color: color.clone(),
+                    count,
+                    limit,
+                });
             }
         }
     }
-    true
+    violations.sort_by(|a, b| (a.reveal_index, &a.color).cmp(&(b.reveal_index, &b.color)));
+    GameValidation {
+        id: game.id,
+        violations,
+    }
 }
 
 fn game_from_line(input: &str) -> Game {
-    let mut max_count = HashMap::new();
-    max_count.insert(Color::Green, 0);
-    max_count.insert(Color::Red, 0);
-    max_count.insert(Color::Blue, 0);
-
     let parts: Vec<&str> = input.split(": ").collect();
     let id_part = parts[0];
     let id = id_part
@@ -86,53 +508,46 @@ fn game_from_line(input: &str) -> Game {
 
     let mut reveals = Vec::new();
     for reveal_str in reveals_strings {
-        let mut reveal = Reveal {
-            cubes: HashMap::new(),
-        };
+        let mut reveal = Reveal { cubes: Vec::new() };
         for color_count in reveal_str.split(", ") {
             let parts: Vec<&str> = color_count.split_whitespace().collect();
             let count = parts[0].parse::<u32>().unwrap();
-            let color = match parts[1] {
-                "red" => Color::Red,
-                "green" => Color::Green,
-                "blue" => Color::Blue,
-                _ => panic!("Unknown color"),
-            };
-            reveal.cubes.insert(color, count);
-            let entry = max_count.entry(color).or_insert(0);
-            if count > *entry {
-                *entry = count;
+            let color = Color::new(parts[1]);
+            // "3 red, 2 red" is five red cubes drawn together.
+            match reveal.cubes.iter_mut().find(|(c, _)| *c == color) {
+                Some((_, total)) => *total += count,
+                None => reveal.cubes.push((color, count)),
             }
         }
         reveals.push(reveal);
     }
 
-    let mut power = 1;
-    for &count in max_count.values() {
-        power *= count;
-    }
-
-    Game { id, reveals, power }
+    Game::new(id, reveals)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    pub fn sample_games() -> Vec<Game> {
+        include_str!("../sample.input")
+            .lines()
+            .map(game_from_line)
+            .collect()
+    }
+
     #[test]
     fn test_game_from_line() {
         let mut game1_reveals = Vec::new();
-        let mut reveal1 = HashMap::new();
-        reveal1.insert(Color::Blue, 3);
-        reveal1.insert(Color::Red, 4);
+        let reveal1 = vec![(Color::new("blue"), 3), (Color::new("red"), 4)];
         game1_reveals.push(Reveal { cubes: reveal1 });
-        let mut reveal2 = HashMap::new();
-        reveal2.insert(Color::Red, 1);
-        reveal2.insert(Color::Green, 2);
-        reveal2.insert(Color::Blue, 6);
+        let reveal2 = vec![
+            (Color::new("red"), 1),
+            (Color::new("green"), 2),
+            (Color::new("blue"), 6),
+        ];
         game1_reveals.push(Reveal { cubes: reveal2 });
-        let mut reveal3 = HashMap::new();
-        reveal3.insert(Color::Green, 2);
+        let reveal3 = vec![(Color::new("green"), 2)];
         game1_reveals.push(Reveal { cubes: reveal3 });
 
         let game1 = Game {
@@ -149,19 +564,19 @@ mod tests {
     #[test]
     fn test_game_from_line2() {
         let mut game4_reveals = Vec::new();
-        let mut reveal1 = HashMap::new();
-        reveal1.insert(Color::Green, 1);
-        reveal1.insert(Color::Red, 3);
-        reveal1.insert(Color::Blue, 6);
+        let reveal1 = vec![
+            (Color::new("green"), 1),
+            (Color::new("red"), 3),
+            (Color::new("blue"), 6),
+        ];
         game4_reveals.push(Reveal { cubes: reveal1 });
-        let mut reveal2 = HashMap::new();
-        reveal2.insert(Color::Green, 3);
-        reveal2.insert(Color::Red, 6);
+        let reveal2 = vec![(Color::new("green"), 3), (Color::new("red"), 6)];
         game4_reveals.push(Reveal { cubes: reveal2 });
-        let mut reveal3 = HashMap::new();
-        reveal3.insert(Color::Green, 3);
-        reveal3.insert(Color::Blue, 15);
-        reveal3.insert(Color::Red, 14);
+        let reveal3 = vec![
+            (Color::new("green"), 3),
+            (Color::new("blue"), 15),
+            (Color::new("red"), 14),
+        ];
         game4_reveals.push(Reveal { cubes: reveal3 });
         let game4 = Game {
             id: 4,
@@ -176,26 +591,181 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_game_from_line_other_colors() {
+        let game = game_from_line("Game 7: 2 yellow, 1 red; 3 purple, 4 green, 2 blue; 1 yellow");
+        assert_eq!(Some(2), game.reveals[0].get(&Color::new("yellow")));
+        assert_eq!(Some(3), game.reveals[1].get(&Color::new("purple")));
+        assert_eq!(48, game.power);
+    }
+
+    #[test]
+    fn test_valid_game_other_colors() {
+        let game = game_from_line("Game 7: 2 yellow, 1 red; 3 purple, 4 green");
+        let bag = bag_from_spec("red=12,green=13,blue=14").unwrap();
+        assert!(!valid_game(&bag, &game));
+        let bag = bag_from_spec("red=12,green=13,blue=14,yellow=2,purple=3").unwrap();
+        assert!(valid_game(&bag, &game));
+    }
+
+    #[test]
+    fn test_validate_game_lists_every_violation() {
+        let game = game_from_line(
+            "Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red, 2 yellow; 15 red, 16 blue",
+        );
+        let validation = validate_game(&default_bag(), &game);
+        assert_eq!(
+            GameValidation {
+                id: 3,
+                violations: vec![
+                    Violation {
+                        reveal_index: 0,
+                        color: Color::new("red"),
+                        count: 20,
+                        limit: Some(12),
+                    },
+                    Violation {
+                        reveal_index: 2,
+                        color: Color::new("yellow"),
+                        count: 2,
+                        limit: None,
+                    },
+                    Violation {
+                        reveal_index: 3,
+                        color: Color::new("blue"),
+                        count: 16,
+                        limit: Some(14),
+                    },
+                    Violation {
+                        reveal_index: 3,
+                        color: Color::new("red"),
+                        count: 15,
+                        limit: Some(12),
+                    },
+                ],
+            },
+            validation
+        );
+        assert!(!validation.is_valid());
+        assert_eq!(
+            vec![8, 2, 2, 3],
+            validation
+                .violations
+                .iter()
+                .map(Violation::excess)
+                .collect::<Vec<u32>>()
+        );
+        assert_eq!(
+            "reveal 1: 20 red exceeds 12 by 8",
+            validation.violations[0].to_string()
+        );
+        assert_eq!(
+            "reveal 3: 2 yellow not in bag",
+            validation.violations[1].to_string()
+        );
+    }
+
+    #[test]
+    fn test_validate_game_valid() {
+        let game = game_from_line("Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green");
+        let validation = validate_game(&default_bag(), &game);
+        assert!(validation.is_valid());
+        assert_eq!(1, validation.id);
+    }
+
+    #[test]
+    fn test_minimal_bag() {
+        let game = game_from_line("Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green");
+        assert_eq!(
+            bag_from_spec("blue=6,red=4,green=2").unwrap(),
+            game.minimal_bag()
+        );
+        assert_eq!("blue=6,green=2,red=4", format_bag(&game.minimal_bag()));
+    }
+
+    #[test]
+    fn test_power_with_missing_color() {
+        let game = game_from_line("Game 2: 3 red, 4 green; 5 red");
+        assert_eq!(0, game.power);
+        assert_eq!(0, game.power_with(&PowerMode::required()));
+        assert_eq!(20, game.power_with(&PowerMode::Observed));
+        assert_eq!(
+            20,
+            game.power_with(&PowerMode::Required(vec![Color::new("red")]))
+        );
+    }
+
+    #[test]
+    fn test_process_input_observed_power() {
+        let input = r#"
+Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 2: 3 red, 4 green; 5 red
+"#;
+        let result = process_input(input.as_bytes(), &[default_bag()], &PowerMode::Observed);
+        assert_eq!((vec![3], 48 + 20), result);
+        let result = process_input(input.as_bytes(), &[default_bag()], &PowerMode::required());
+        assert_eq!((vec![3], 48), result);
+    }
+
+    #[test]
+    fn test_game_display() {
+        let game = game_from_line(
+            "Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red",
+        );
+        assert_eq!(
+            "Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red",
+            game.to_string()
+        );
+        assert_eq!("1 green, 3 red, 6 blue", game.reveals[0].to_string());
+    }
+
+    #[test]
+    fn test_game_round_trip() {
+        let inputs = [
+            include_str!("../sample.input"),
+            include_str!("../puzzle.input"),
+            "Game 7: 2 yellow, 1 red; 3 purple, 4 green, 2 blue; 1 yellow\nGame 8: 0 red",
+        ];
+        for input in inputs {
+            for line in input.lines().filter(|line| !line.trim().is_empty()) {
+                let game = game_from_line(line);
+                assert_eq!(line, game.to_string());
+                assert_eq!(game, game_from_line(&game.to_string()));
+            }
+        }
+    }
+
     #[test]
     fn test_valid_game1() {
-        let mut max_reveals = HashMap::new();
-        max_reveals.insert(Color::Green, 13);
-        max_reveals.insert(Color::Red, 12);
-        max_reveals.insert(Color::Blue, 14);
+        let max_reveals = vec![
+            (Color::new("green"), 13),
+            (Color::new("red"), 12),
+            (Color::new("blue"), 14),
+        ];
         let game1 = game_from_line("Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green");
-        assert_eq!(valid_game(&Reveal { cubes: max_reveals }, &game1), true);
+        assert!(valid_game(&Reveal { cubes: max_reveals }, &game1));
     }
 
     #[test]
     fn test_valid_game3() {
-        let mut max_reveals = HashMap::new();
-        max_reveals.insert(Color::Green, 13);
-        max_reveals.insert(Color::Red, 12);
-        max_reveals.insert(Color::Blue, 14);
+        let max_reveals = vec![
+            (Color::new("green"), 13),
+            (Color::new("red"), 12),
+            (Color::new("blue"), 14),
+        ];
         let game3 = game_from_line(
             "Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red",
         );
-        assert_eq!(valid_game(&Reveal { cubes: max_reveals }, &game3), false);
+        assert!(!valid_game(&Reveal { cubes: max_reveals }, &game3));
+    }
+
+    #[test]
+    fn test_repeated_colors_are_merged() {
+        let game = game_from_line("Game 1: 10 red, 10 red, 1 green, 1 blue");
+        assert_eq!(Some(20), game.reveals[0].get(&Color::new("red")));
+        assert_eq!("Game 1: 20 red, 1 green, 1 blue", game.to_string());
+        assert!(!valid_game(&default_bag(), &game));
+        assert_eq!(Some(20), game.minimal_bag().get(&Color::new("red")));
     }
 
     #[test]
@@ -207,7 +777,60 @@ Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
 Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
 Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green
 "#;
-        let result = process_input(input.as_bytes());
-        assert_eq!((8, 2286), result);
+        let result = process_input(input.as_bytes(), &[default_bag()], &PowerMode::required());
+        assert_eq!((vec![8], 2286), result);
+    }
+
+    #[test]
+    fn test_process_input_multiple_bags() {
+        let input = r#"
+Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
+Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
+Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
+Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green
+"#;
+        let bags = [
+            bag_from_spec("red=12,green=13,blue=14").unwrap(),
+            bag_from_spec("red=20,green=13,blue=15").unwrap(),
+            bag_from_spec("red=4,green=3").unwrap(),
+        ];
+        let result = process_input(input.as_bytes(), &bags, &PowerMode::required());
+        assert_eq!((vec![8, 15, 0], 2286), result);
+    }
+
+    #[test]
+    fn test_bag_from_spec() {
+        let cubes = vec![
+            (Color::new("red"), 12),
+            (Color::new("green"), 13),
+            (Color::new("blue"), 14),
+        ];
+        assert_eq!(
+            Ok(Reveal { cubes }),
+            bag_from_spec("red=12, green=13,blue = 14")
+        );
+        assert!(bag_from_spec("red=12,=1").is_err());
+        assert!(bag_from_spec("red12").is_err());
+        assert!(bag_from_spec("red=-1").is_err());
+        assert!(bag_from_spec("red=1,red=2").is_err());
+    }
+
+    #[test]
+    fn test_bags_from_file() {
+        let contents = r#"
+# candidate bags
+red=12,green=13,blue=14
+
+red=1,green=1,blue=1
+"#;
+        let bags = bags_from_file(contents).unwrap();
+        assert_eq!(
+            vec!["red=12,green=13,blue=14", "red=1,green=1,blue=1"],
+            bags.iter()
+                .map(|(spec, _)| spec.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!(bag_from_spec("red=1,green=1,blue=1").unwrap(), bags[1].1);
     }
 }
@@ -0,0 +1,337 @@
+use crate::{Color, Game, PowerMode};
+
+// A small filter language over games:
+//
+//     expr       := and ("||" and)*
+//     and        := unary ("&&" unary)*
+//     unary      := "!" unary | "(" expr ")" | comparison
+//     comparison := value (">" | ">=" | "<" | "<=" | "==" | "!=") value
+//     value      := number | "reveals" | "power" | "id"
+//                 | ("max" | "min" | "sum") "(" color ")"
+//
+// Like the --summary statistics, only the reveals that mention a color count
+// towards it: a reveal lacking blue doesn't pull `min(blue)` down to 0. A
+// game that never shows the color gets 0 for all three. `power` is taken in
+// whichever --power mode is selected.
+
+#[derive(Debug, PartialEq)]
+pub enum Query {
+    Or(Box<Query>, Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Compare(Value, Op, Value),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Number(u64),
+    Max(Color),
+    Min(Color),
+    Sum(Color),
+    Reveals,
+    Power,
+    Id,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Number(u64),
+    Ident(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+        };
+        let query = parser.expr()?;
+        match parser.peek() {
+            None => Ok(query),
+            Some(token) => Err(format!("unexpected {:?} after expression", token)),
+        }
+    }
+
+    pub fn matches(&self, game: &Game, power_mode: &PowerMode) -> bool {
+        match self {
+            Query::Or(left, right) => {
+                left.matches(game, power_mode) || right.matches(game, power_mode)
+            }
+            Query::And(left, right) => {
+                left.matches(game, power_mode) && right.matches(game, power_mode)
+            }
+            Query::Not(inner) => !inner.matches(game, power_mode),
+            Query::Compare(left, op, right) => {
+                let (left, right) = (
+                    left.evaluate(game, power_mode),
+                    right.evaluate(game, power_mode),
+                );
+                match op {
+                    Op::Lt => left < right,
+                    Op::Le => left <= right,
+                    Op::Gt => left > right,
+                    Op::Ge => left >= right,
+                    Op::Eq => left == right,
+                    Op::Ne => left != right,
+                }
+            }
+        }
+    }
+}
+
+impl Value {
+    fn evaluate(&self, game: &Game, power_mode: &PowerMode) -> u64 {
+        match self {
+            Value::Number(number) => *number,
+            Value::Max(color) => counts(game, color).max().unwrap_or(0),
+            Value::Min(color) => counts(game, color).min().unwrap_or(0),
+            Value::Sum(color) => counts(game, color).sum(),
+            Value::Reveals => game.reveals.len() as u64,
+            Value::Power => game.power_with(power_mode) as u64,
+            Value::Id => game.id as u64,
+        }
+    }
+}
+
+fn counts<'a>(game: &'a Game, color: &'a Color) -> impl Iterator<Item = u64> + 'a {
+    game.reveals
+        .iter()
+        .filter_map(move |reveal| reveal.get(color).map(u64::from))
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| "unexpected end of query".to_string())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        let token = self.next()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(format!("expected {:?}, found {:?}", expected, token))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Query, String> {
+        let mut query = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query, String> {
+        let mut query = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            query = Query::And(Box::new(query), Box::new(self.unary()?));
+        }
+        Ok(query)
+    }
+
+    fn unary(&mut self) -> Result<Query, String> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.position += 1;
+                Ok(Query::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Open) => {
+                self.position += 1;
+                let query = self.expr()?;
+                self.expect(Token::Close)?;
+                Ok(query)
+            }
+            _ => {
+                let left = self.value()?;
+                let op = match self.next()? {
+                    Token::Op(op) => op,
+                    token => return Err(format!("expected comparison, found {:?}", token)),
+                };
+                let right = self.value()?;
+                Ok(Query::Compare(left, op, right))
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.next()? {
+            Token::Number(number) => Ok(Value::Number(number)),
+            Token::Ident(name) => match name.as_str() {
+                "reveals" => Ok(Value::Reveals),
+                "power" => Ok(Value::Power),
+                "id" => Ok(Value::Id),
+                "max" | "min" | "sum" => {
+                    self.expect(Token::Open)?;
+                    let color = match self.next()? {
+                        Token::Ident(color) => Color::new(&color),
+                        token => return Err(format!("expected color, found {:?}", token)),
+                    };
+                    self.expect(Token::Close)?;
+                    Ok(match name.as_str() {
+                        "max" => Value::Max(color),
+                        "min" => Value::Min(color),
+                        _ => Value::Sum(color),
+                    })
+                }
+                _ => Err(format!("unknown value {:?}", name)),
+            },
+            token => Err(format!("expected value, found {:?}", token)),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).copied();
+        if c.is_whitespace() {
+            index += 1;
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = index;
+            while index < chars.len() && chars[index].is_ascii_digit() {
+                index += 1;
+            }
+            let digits: String = chars[start..index].iter().collect();
+            let number = digits
+                .parse::<u64>()
+                .map_err(|err| format!("invalid number {:?}: {}", digits, err))?;
+            tokens.push(Token::Number(number));
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let start = index;
+            while index < chars.len()
+                && (chars[index].is_alphanumeric() || chars[index] == '_' || chars[index] == '-')
+            {
+                index += 1;
+            }
+            tokens.push(Token::Ident(chars[start..index].iter().collect()));
+            continue;
+        }
+        let (token, width) = match (c, next) {
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            _ => return Err(format!("unexpected {:?} at column {}", c, index + 1)),
+        };
+        tokens.push(token);
+        index += width;
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::sample_games;
+
+    fn matching_ids(query: &str) -> Vec<u32> {
+        let query = Query::parse(query).unwrap();
+        sample_games()
+            .iter()
+            .filter(|game| query.matches(game, &PowerMode::required()))
+            .map(|game| game.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        assert_eq!(
+            Query::parse("id == 1 || id == 2 && power > 3"),
+            Ok(Query::Or(
+                Box::new(Query::Compare(Value::Id, Op::Eq, Value::Number(1))),
+                Box::new(Query::And(
+                    Box::new(Query::Compare(Value::Id, Op::Eq, Value::Number(2))),
+                    Box::new(Query::Compare(Value::Power, Op::Gt, Value::Number(3))),
+                )),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Query::parse("").is_err());
+        assert!(Query::parse("max(red)").is_err());
+        assert!(Query::parse("max(red > 1").is_err());
+        assert!(Query::parse("reds > 1").is_err());
+        assert!(Query::parse("power > 1 power").is_err());
+        assert!(Query::parse("power = 1").is_err());
+        assert!(Query::parse("(power > 1").is_err());
+    }
+
+    #[test]
+    fn test_matches_sample() {
+        assert_eq!(vec![3, 4], matching_ids("max(red) > 10"));
+        assert_eq!(vec![3], matching_ids("max(red) > 10 && max(blue) < 10"));
+        // Game 3's last reveal has no blue, but its min(blue) is still 5.
+        assert_eq!(vec![3], matching_ids("max(red) > 10 && min(blue) < 6"));
+        assert_eq!(vec![1, 2, 3, 4], matching_ids("reveals >= 3"));
+        assert_eq!(vec![3, 4], matching_ids("power > 500"));
+        assert_eq!(vec![1, 2, 5], matching_ids("!(power > 500)"));
+        assert_eq!(vec![1, 5], matching_ids("sum(green) == 4 || id == 5"));
+        assert_eq!(
+            vec![1, 2, 5],
+            matching_ids("min(red) <= 1 && max(green) <= 3 || id==5")
+        );
+        assert_eq!(Vec::<u32>::new(), matching_ids("min(red) < 1"));
+        assert_eq!(vec![1, 2, 3, 4, 5], matching_ids("min(purple) == 0"));
+        assert_eq!(Vec::<u32>::new(), matching_ids("max(purple) != 0"));
+    }
+
+    #[test]
+    fn test_matches_power_mode() {
+        use crate::game_from_line;
+
+        // Game 1 has no blue, so its required power is 0.
+        let game = game_from_line("Game 1: 4 red, 5 green");
+        let query = Query::parse("power == 20").unwrap();
+        assert!(!query.matches(&game, &PowerMode::required()));
+        assert!(query.matches(&game, &PowerMode::Observed));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::rng::Rng;
+use crate::{Game, Reveal};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RevealCount {
+    Fixed(u32),
+    Uniform(u32, u32),
+}
+
+impl RevealCount {
+    pub fn from_spec(spec: &str) -> Result<RevealCount, String> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<u32>()
+                .map_err(|err| format!("invalid reveal count {:?}: {}", spec, err))
+        };
+        let count = match spec.split_once("..") {
+            Some((low, high)) => RevealCount::Uniform(parse(low)?, parse(high)?),
+            None => RevealCount::Fixed(parse(spec)?),
+        };
+        match count {
+            RevealCount::Fixed(0) => Err("a game needs at least one reveal".to_string()),
+            RevealCount::Uniform(low, high) if low == 0 || low > high => {
+                Err(format!("invalid reveal count range {:?}", spec))
+            }
+            _ => Ok(count),
+        }
+    }
+
+    fn sample(&self, rng: &mut Rng) -> u32 {
+        match *self {
+            RevealCount::Fixed(count) => count,
+            RevealCount::Uniform(low, high) => low + rng.below((high - low + 1) as u64) as u32,
+        }
+    }
+}
+
+// Games are numbered from 1. Every reveal draws between one cube and the
+// whole bag, without replacement, and lists its colors in random order.
+pub fn simulate(
+    bag: &Reveal,
+    games: u32,
+    reveals: RevealCount,
+    seed: u64,
+) -> Result<Vec<Game>, String> {
+    let total = bag.total();
+    if total == 0 {
+        return Err("cannot simulate an empty bag".to_string());
+    }
+    let mut rng = Rng::new(seed);
+    let games = (1..=games)
+        .map(|id| {
+            let reveal_count = reveals.sample(&mut rng);
+            let reveals = (0..reveal_count)
+                .map(|_| {
+                    let size = 1 + rng.below(total as u64) as u32;
+                    let mut reveal = draw_reveal(bag, size, &mut rng);
+                    shuffle(&mut reveal.cubes, &mut rng);
+                    reveal
+                })
+                .collect();
+            Game::new(id, reveals)
+        })
+        .collect();
+    Ok(games)
+}
+
+pub fn draw_reveal(bag: &Reveal, size: u32, rng: &mut Rng) -> Reveal {
+    let mut cubes: Vec<usize> = bag
+        .cubes
+        .iter()
+        .enumerate()
+        .flat_map(|(index, &(_, count))| std::iter::repeat_n(index, count as usize))
+        .collect();
+    let size = (size as usize).min(cubes.len());
+    let mut counts = vec![0; bag.cubes.len()];
+    for drawn in 0..size {
+        let pick = drawn + rng.below((cubes.len() - drawn) as u64) as usize;
+        cubes.swap(drawn, pick);
+        counts[cubes[drawn]] += 1;
+    }
+    Reveal {
+        cubes: bag
+            .cubes
+            .iter()
+            .zip(counts)
+            .filter(|&(_, count)| count > 0)
+            .map(|((color, _), count)| (color.clone(), count))
+            .collect(),
+    }
+}
+
+fn shuffle<T>(items: &mut [T], rng: &mut Rng) {
+    for index in (1..items.len()).rev() {
+        items.swap(index, rng.below(index as u64 + 1) as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bag_from_spec, game_from_line, valid_game, Color};
+
+    #[test]
+    fn test_draw_reveal_without_replacement() {
+        let bag = bag_from_spec("red=2,green=1").unwrap();
+        let mut rng = Rng::new(9);
+        for _ in 0..50 {
+            let reveal = draw_reveal(&bag, 3, &mut rng);
+            assert_eq!(Some(2), reveal.get(&Color::new("red")));
+            assert_eq!(Some(1), reveal.get(&Color::new("green")));
+            let reveal = draw_reveal(&bag, 2, &mut rng);
+            assert_eq!(2, reveal.total());
+            assert!(reveal.get(&Color::new("red")).unwrap_or(0) <= 2);
+        }
+    }
+
+    #[test]
+    fn test_reveal_count_from_spec() {
+        assert_eq!(Ok(RevealCount::Fixed(3)), RevealCount::from_spec("3"));
+        assert_eq!(
+            Ok(RevealCount::Uniform(1, 6)),
+            RevealCount::from_spec("1..6")
+        );
+        assert!(RevealCount::from_spec("0").is_err());
+        assert!(RevealCount::from_spec("4..2").is_err());
+        assert!(RevealCount::from_spec("x").is_err());
+    }
+
+    #[test]
+    fn test_simulate_is_deterministic() {
+        let bag = bag_from_spec("red=12,green=13,blue=14").unwrap();
+        let a = simulate(&bag, 20, RevealCount::Uniform(1, 6), 7).unwrap();
+        let b = simulate(&bag, 20, RevealCount::Uniform(1, 6), 7).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(
+            a,
+            simulate(&bag, 20, RevealCount::Uniform(1, 6), 8).unwrap()
+        );
+        assert_eq!(
+            (1..=20).collect::<Vec<u32>>(),
+            a.iter().map(|game| game.id).collect::<Vec<u32>>()
+        );
+    }
+
+    #[test]
+    fn test_simulated_games_are_valid_for_their_bag() {
+        let bags = [
+            "red=12,green=13,blue=14",
+            "red=1",
+            "yellow=3,purple=9,red=2",
+            "red=0,green=5",
+        ];
+        for spec in bags {
+            let bag = bag_from_spec(spec).unwrap();
+            for seed in 0..25 {
+                let reveals = RevealCount::Uniform(1, 1 + seed as u32 % 7);
+                for game in simulate(&bag, 10, reveals, seed).unwrap() {
+                    assert!(valid_game(&bag, &game), "{} with {}", game, spec);
+                    assert!(game.reveals.iter().all(|reveal| reveal.total() > 0));
+                    assert_eq!(game, game_from_line(&game.to_string()));
+                }
+            }
+        }
+        assert!(simulate(
+            &bag_from_spec("red=0").unwrap(),
+            1,
+            RevealCount::Fixed(1),
+            0
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::BTreeMap;
+
+use crate::grid::Grid;
+use crate::{process_input, Cell, Gear, GearRule, Options, PartNumber, SchematicError, Storage};
+
+// An editable schematic that keeps the part sum and gear ratios up to date
+// cell by cell. An edit can only split, merge or change the numbers touching
+// it in its own row, and only symbols next to one of those numbers (or the
+// edited cell itself) can gain or lose a gear, so that's all set() looks at.
+// Uses the default adjacency and tokenizer.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Slot {
+    Empty,
+    Number(usize),
+    Symbol,
+}
+
+pub struct Schematic {
+    rule: GearRule,
+    rows: Vec<Vec<char>>,
+    slots: Grid<Slot>,
+    // Indexed by the ids in `slots`; ids of removed numbers are reused.
+    numbers: Vec<Option<PartNumber>>,
+    free: Vec<usize>,
+    // Keyed by (y, x) so iteration is in reading order.
+    gears: BTreeMap<(u32, u32), Gear>,
+    overfull_gears: BTreeMap<(u32, u32), Gear>,
+    // Both kept wider than the i64 they're reported as, so that taking one
+    // number or ratio out before putting another in can't overflow on the way.
+    sum: i128,
+    gear_ratios: i128,
+}
+
+impl Schematic {
+    pub fn parse(input: &str, rule: GearRule) -> Result<Schematic, SchematicError> {
+        let options = Options {
+            rule,
+            storage: Storage::Dense,
+            ..Options::default()
+        };
+        let analysis = process_input(input.as_bytes(), &options)?;
+        let (width, height) = (analysis.grid.width(), analysis.grid.height());
+        let mut slots = Grid::new(width, height, Slot::Empty);
+        for y in 0..height {
+            for x in 0..width {
+                *slots.get_mut(x, y).unwrap() = match analysis.grid.get(x, y) {
+                    Some(&Cell::Number(id)) => Slot::Number(id),
+                    Some(&Cell::Symbol(_)) => Slot::Symbol,
+                    _ => Slot::Empty,
+                };
+            }
+        }
+        let by_position = |gears: Vec<Gear>| {
+            gears
+                .into_iter()
+                .map(|gear| ((gear.y, gear.x), gear))
+                .collect()
+        };
+        Ok(Schematic {
+            rule,
+            rows: input
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.chars().collect())
+                .collect(),
+            slots,
+            numbers: analysis.part_numbers.into_iter().map(Some).collect(),
+            free: Vec::new(),
+            gears: by_position(analysis.gears),
+            overfull_gears: by_position(analysis.overfull_gears),
+            sum: analysis.sum.into(),
+            gear_ratios: analysis.gear_ratios.into(),
+        })
+    }
+
+    pub fn sum(&self) -> i64 {
+        self.sum as i64
+    }
+
+    pub fn gear_ratios(&self) -> i64 {
+        self.gear_ratios as i64
+    }
+
+    pub fn overfull_gears(&self) -> impl Iterator<Item = &Gear> {
+        self.overfull_gears.values()
+    }
+
+    // Edits must stay inside the schematic as it was parsed; cells past the
+    // end of a short row are filled with '.' when written to.
+    pub fn set(&mut self, x: usize, y: usize, c: char) -> Result<(), String> {
+        if x >= self.slots.width() || y >= self.slots.height() {
+            return Err(format!("{},{} is outside the schematic", x, y));
+        }
+        if c.is_control() || c.is_whitespace() {
+            return Err(format!("can't set a cell to {:?}", c));
+        }
+        if self.rows[y].len() <= x {
+            self.rows[y].resize(x + 1, '.');
+        }
+        if c.is_ascii_digit() && self.digit_run(x, y, c).parse::<i64>().is_err() {
+            return Err("part number does not fit in an i64".to_string());
+        }
+        if self.rows[y][x] == c {
+            return Ok(());
+        }
+        let previous = self.rows[y][x];
+        let result = self.apply(x, y, c);
+        if result.is_err() {
+            // The schematic fit before the edit, so putting the old cell
+            // back can't overflow.
+            self.apply(x, y, previous).unwrap();
+        }
+        result
+    }
+
+    // Fails if a gear's ratio or either total no longer fits in an i64; the
+    // schematic is left half-updated for set() to roll back.
+    fn apply(&mut self, x: usize, y: usize, c: char) -> Result<(), String> {
+        let was_symbol = self.slots.get(x, y) == Some(&Slot::Symbol);
+        let mut removed = Vec::new();
+        for cx in x.saturating_sub(1)..=x + 1 {
+            if let Some(&Slot::Number(id)) = self.slots.get(cx, y) {
+                if !removed.contains(&id) {
+                    removed.push(id);
+                }
+            }
+        }
+        let mut dirty = Vec::new();
+        let (mut low, mut high) = (x, x);
+        for id in removed {
+            let number = self.numbers[id].take().unwrap();
+            self.free.push(id);
+            if number.real_part {
+                self.sum -= i128::from(number.number);
+            }
+            low = low.min(number.x as usize);
+            high = high.max((number.x + number.width - 1) as usize);
+            self.symbols_around(&number, &mut dirty);
+            for cx in number.x..number.x + number.width {
+                *self.slots.get_mut(cx as usize, y).unwrap() = Slot::Empty;
+            }
+        }
+
+        self.rows[y][x] = c;
+        let is_symbol = c != '.' && !c.is_ascii_digit();
+        if is_symbol {
+            *self.slots.get_mut(x, y).unwrap() = Slot::Symbol;
+        } else if was_symbol {
+            *self.slots.get_mut(x, y).unwrap() = Slot::Empty;
+        }
+
+        // The cells just outside low..=high aren't digits, so re-reading the
+        // segment finds exactly the numbers that replace the removed ones.
+        let mut recheck = Vec::new();
+        let mut cx = low;
+        while cx <= high {
+            if !self.rows[y][cx].is_ascii_digit() {
+                cx += 1;
+                continue;
+            }
+            let start = cx;
+            while cx <= high && self.rows[y][cx].is_ascii_digit() {
+                cx += 1;
+            }
+            let digits: String = self.rows[y][start..cx].iter().collect();
+            let id = self.free.pop().unwrap_or_else(|| {
+                self.numbers.push(None);
+                self.numbers.len() - 1
+            });
+            for nx in start..cx {
+                *self.slots.get_mut(nx, y).unwrap() = Slot::Number(id);
+            }
+            self.numbers[id] = Some(PartNumber {
+                x: start as u32,
+                width: (cx - start) as u32,
+                y: y as u32,
+                number: digits.parse::<i64>().unwrap(),
+                real_part: false,
+            });
+            recheck.push(id);
+        }
+        for &id in &recheck {
+            let number = self.numbers[id].as_ref().unwrap();
+            let mut symbols = Vec::new();
+            self.symbols_around(number, &mut symbols);
+            if !symbols.is_empty() {
+                self.sum += i128::from(number.number);
+                self.numbers[id].as_mut().unwrap().real_part = true;
+            }
+            for symbol in symbols {
+                if !dirty.contains(&symbol) {
+                    dirty.push(symbol);
+                }
+            }
+        }
+
+        if was_symbol != is_symbol {
+            let neighbors: Vec<(usize, usize)> = self.slots.neighbors8(x, y).collect();
+            for (nx, ny) in neighbors {
+                if let Some(&Slot::Number(id)) = self.slots.get(nx, ny) {
+                    if !recheck.contains(&id) {
+                        recheck.push(id);
+                        self.refresh_real(id);
+                    }
+                }
+            }
+        }
+        if (was_symbol || is_symbol) && !dirty.contains(&(x, y)) {
+            dirty.push((x, y));
+        }
+        let mut result = Ok(());
+        for (sx, sy) in dirty {
+            // Every gear is refreshed even after one fails, so that rolling
+            // back finds them all consistent.
+            let refreshed = self.refresh_gear(sx, sy);
+            result = result.and(refreshed);
+        }
+        result?;
+        if i64::try_from(self.sum).is_err() {
+            return Err("sum would no longer fit in an i64".to_string());
+        }
+        if i64::try_from(self.gear_ratios).is_err() {
+            return Err("gear ratios would no longer fit in an i64".to_string());
+        }
+        Ok(())
+    }
+
+    // The digits the row would have around (x, y) once it holds `c`.
+    fn digit_run(&self, x: usize, y: usize, c: char) -> String {
+        let row = &self.rows[y];
+        let digit = |cx: usize| row.get(cx).is_some_and(|c| c.is_ascii_digit());
+        let mut start = x;
+        while start > 0 && digit(start - 1) {
+            start -= 1;
+        }
+        let mut end = x + 1;
+        while digit(end) {
+            end += 1;
+        }
+        let mut run: String = row[start..x].iter().collect();
+        run.push(c);
+        run.extend(&row[x + 1..end]);
+        run
+    }
+
+    fn symbols_around(&self, number: &PartNumber, symbols: &mut Vec<(usize, usize)>) {
+        let ring =
+            self.slots
+                .span_neighbors(number.x as usize, number.y as usize, number.width as usize);
+        for (x, y) in ring {
+            if self.slots.get(x, y) == Some(&Slot::Symbol) && !symbols.contains(&(x, y)) {
+                symbols.push((x, y));
+            }
+        }
+    }
+
+    fn refresh_real(&mut self, id: usize) {
+        let number = self.numbers[id].as_ref().unwrap();
+        let mut symbols = Vec::new();
+        self.symbols_around(number, &mut symbols);
+        let real_part = !symbols.is_empty();
+        if real_part != number.real_part {
+            let number = i128::from(number.number);
+            self.sum += if real_part { number } else { -number };
+            self.numbers[id].as_mut().unwrap().real_part = real_part;
+        }
+    }
+
+    fn refresh_gear(&mut self, x: usize, y: usize) -> Result<(), String> {
+        let key = (y as u32, x as u32);
+        if let Some(gear) = self.gears.remove(&key) {
+            self.gear_ratios -= i128::from(self.rule.ratio(&gear.numbers).unwrap());
+        }
+        self.overfull_gears.remove(&key);
+        if self.rows[y].get(x) != Some(&self.rule.symbol) {
+            return Ok(());
+        }
+        let mut ids: Vec<usize> = Vec::new();
+        for (nx, ny) in self.slots.neighbors8(x, y) {
+            if let Some(&Slot::Number(id)) = self.slots.get(nx, ny) {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        let gear = Gear {
+            x: x as u32,
+            y: y as u32,
+            numbers: ids
+                .iter()
+                .map(|&id| self.numbers[id].as_ref().unwrap().number)
+                .collect(),
+        };
+        if self.rule.accepts(gear.numbers.len()) {
+            let ratio = self.rule.ratio(&gear.numbers).ok_or_else(|| {
+                format!("gear at {},{} would take the gear ratios past an i64", x, y)
+            })?;
+            self.gear_ratios += i128::from(ratio);
+            self.gears.insert(key, gear);
+        } else if self.rule.overfull(gear.numbers.len()) {
+            self.overfull_gears.insert(key, gear);
+        }
+        Ok(())
+    }
+}
+
+// Parses "X,Y,C" as given to --set; C may itself be a comma.
+pub fn parse_edit(value: &str) -> Result<(usize, usize, char), String> {
+    let mut fields = value.splitn(3, ',');
+    let mut coordinate = || fields.next().and_then(|field| field.parse::<usize>().ok());
+    let (x, y) = (coordinate(), coordinate());
+    let mut chars = fields.next().unwrap_or("").chars();
+    match (x, y, chars.next(), chars.next()) {
+        (Some(x), Some(y), Some(c), None) => Ok((x, y, c)),
+        _ => Err(format!("expected X,Y,C but got {:?}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+    use crate::tests::{analyze_with, random_schematic};
+
+    fn assert_matches_process_input(schematic: &Schematic) {
+        let input: String = schematic
+            .rows
+            .iter()
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect();
+        let analysis = analyze_with(
+            &input,
+            &Options {
+                rule: schematic.rule,
+                ..Options::default()
+            },
+        );
+        assert_eq!(
+            (analysis.sum, analysis.gear_ratios),
+            (schematic.sum(), schematic.gear_ratios()),
+            "{}",
+            input
+        );
+        assert_eq!(
+            analysis.gears.iter().collect::<Vec<&Gear>>(),
+            schematic.gears.values().collect::<Vec<&Gear>>(),
+            "{}",
+            input
+        );
+        assert_eq!(
+            analysis.overfull_gears.iter().collect::<Vec<&Gear>>(),
+            schematic.overfull_gears().collect::<Vec<&Gear>>(),
+            "{}",
+            input
+        );
+        let mut expected: Vec<(u32, u32, i64, bool)> = analysis
+            .part_numbers
+            .iter()
+            .map(|n| (n.y, n.x, n.number, n.real_part))
+            .collect();
+        let mut numbers: Vec<(u32, u32, i64, bool)> = schematic
+            .numbers
+            .iter()
+            .flatten()
+            .map(|n| (n.y, n.x, n.number, n.real_part))
+            .collect();
+        expected.sort_unstable();
+        numbers.sort_unstable();
+        assert_eq!(expected, numbers, "{}", input);
+    }
+
+    #[test]
+    fn test_set_splits_and_merges_numbers() {
+        let mut schematic = Schematic::parse("12.34\n..*..\n", GearRule::default()).unwrap();
+        assert_eq!((46, 12 * 34), (schematic.sum(), schematic.gear_ratios()));
+        schematic.set(2, 0, '5').unwrap();
+        assert_eq!((12534, 0), (schematic.sum(), schematic.gear_ratios()));
+        schematic.set(1, 0, '#').unwrap();
+        assert_eq!((1 + 534, 0), (schematic.sum(), schematic.gear_ratios()));
+        schematic.set(2, 0, '.').unwrap();
+        assert_eq!((1 + 34, 0), (schematic.sum(), schematic.gear_ratios()));
+        schematic.set(2, 1, '.').unwrap();
+        assert_eq!((1, 0), (schematic.sum(), schematic.gear_ratios()));
+        assert_matches_process_input(&schematic);
+    }
+
+    #[test]
+    fn test_set_rejects_bad_edits() {
+        let input = "922337203685477580..1\n..\n";
+        let mut schematic = Schematic::parse(input, GearRule::default()).unwrap();
+        assert!(schematic.set(21, 0, '1').is_err());
+        assert!(schematic.set(0, 2, '1').is_err());
+        assert!(schematic.set(0, 1, '\t').is_err());
+        assert!(schematic.set(0, 1, ' ').is_err());
+        schematic.set(18, 0, '7').unwrap();
+        assert_eq!(
+            Err("part number does not fit in an i64".to_string()),
+            schematic.set(19, 0, '0')
+        );
+        // Past the end of a short row.
+        schematic.set(5, 1, '*').unwrap();
+        assert_eq!(vec!['.', '.', '.', '.', '.', '*'], schematic.rows[1]);
+        assert_eq!(9223372036854775807, schematic.sum());
+        assert_matches_process_input(&schematic);
+        assert_eq!(
+            Err("sum would no longer fit in an i64".to_string()),
+            schematic.set(19, 0, '*')
+        );
+        assert_eq!('.', schematic.rows[0][19]);
+        assert_matches_process_input(&schematic);
+    }
+
+    #[test]
+    fn test_set_rejects_overflowing_ratios() {
+        let rule = GearRule {
+            neighbors: crate::NeighborCount::AtLeast(2),
+            ..GearRule::default()
+        };
+        let mut schematic =
+            Schematic::parse("99999.99999\n99999.99999\n99999.99999\n", rule).unwrap();
+        assert_eq!(
+            Err("gear at 5,1 would take the gear ratios past an i64".to_string()),
+            schematic.set(5, 1, '*')
+        );
+        assert_eq!('.', schematic.rows[1][5]);
+        assert_matches_process_input(&schematic);
+
+        let mut schematic = Schematic::parse(
+            "3037000499*3037000499\n.\n3037000499.3037000499\n",
+            GearRule::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            Err("gear ratios would no longer fit in an i64".to_string()),
+            schematic.set(10, 2, '*')
+        );
+        assert_matches_process_input(&schematic);
+        schematic.set(10, 0, '.').unwrap();
+        schematic.set(10, 2, '*').unwrap();
+        assert_matches_process_input(&schematic);
+    }
+
+    #[test]
+    fn test_parse_edit() {
+        assert_eq!(Ok((3, 4, ',')), parse_edit("3,4,,"));
+        assert_eq!(Ok((0, 12, '*')), parse_edit("0,12,*"));
+        assert!(parse_edit("3,4").is_err());
+        assert!(parse_edit("3,4,ab").is_err());
+        assert!(parse_edit("-1,4,*").is_err());
+    }
+
+    #[test]
+    fn test_random_edits_match_process_input() {
+        let mut rng = Rng::new(48);
+        let cells = b"0123456789.*#*";
+        for _ in 0..200 {
+            let density = rng.below(100);
+            let input = random_schematic(&mut rng, density);
+            let mut rule = GearRule::default();
+            if rng.below(2) == 0 {
+                crate::apply_gear_flag(&mut rule, "--gear-count", "1+").unwrap();
+                crate::apply_gear_flag(&mut rule, "--gear-aggregate", "sum").unwrap();
+            }
+            let mut schematic = Schematic::parse(&input, rule).unwrap();
+            let (width, height) = (schematic.slots.width(), schematic.slots.height());
+            for _ in 0..40 {
+                let x = rng.below(width as u64) as usize;
+                let y = rng.below(height as u64) as usize;
+                let c = cells[rng.below(cells.len() as u64) as usize] as char;
+                if schematic.set(x, y, c).is_ok() {
+                    assert_matches_process_input(&schematic);
+                }
+            }
+        }
+    }
+}
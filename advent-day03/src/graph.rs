@@ -0,0 +1,349 @@
+use std::io::{self, Write};
+
+use crate::{Analysis, SchematicError};
+
+// A cluster is every symbol and number reachable from one symbol by hopping
+// between symbols and the numbers they touch. Numbers touching no symbol
+// aren't parts, so they never form a cluster of their own.
+#[derive(Debug, PartialEq)]
+pub struct Component {
+    pub symbols: Vec<usize>,
+    pub numbers: Vec<usize>,
+    pub sum: i64,
+}
+
+pub fn components(analysis: &Analysis) -> Result<Vec<Component>, SchematicError> {
+    let mut symbols_of: Vec<Vec<usize>> = vec![Vec::new(); analysis.part_numbers.len()];
+    for (symbol, numbers) in analysis.adjacent_numbers.iter().enumerate() {
+        for &number in numbers {
+            symbols_of[number].push(symbol);
+        }
+    }
+    let mut seen_symbols = vec![false; analysis.parts.len()];
+    let mut seen_numbers = vec![false; analysis.part_numbers.len()];
+    let mut components = Vec::new();
+    for start in 0..analysis.parts.len() {
+        if seen_symbols[start] || analysis.adjacent_numbers[start].is_empty() {
+            continue;
+        }
+        seen_symbols[start] = true;
+        let mut symbols = Vec::new();
+        let mut numbers = Vec::new();
+        let mut pending = vec![start];
+        while let Some(symbol) = pending.pop() {
+            symbols.push(symbol);
+            for &number in &analysis.adjacent_numbers[symbol] {
+                if seen_numbers[number] {
+                    continue;
+                }
+                seen_numbers[number] = true;
+                numbers.push(number);
+                for &next in &symbols_of[number] {
+                    if !seen_symbols[next] {
+                        seen_symbols[next] = true;
+                        pending.push(next);
+                    }
+                }
+            }
+        }
+        symbols.sort_unstable();
+        numbers.sort_unstable();
+        let mut sum: i64 = 0;
+        for &number in &numbers {
+            let part_number = &analysis.part_numbers[number];
+            sum = sum
+                .checked_add(part_number.number)
+                .ok_or_else(|| analysis.sum_overflow(part_number))?;
+        }
+        components.push(Component {
+            symbols,
+            numbers,
+            sum,
+        });
+    }
+    Ok(components)
+}
+
+// Symbols are s<index> and numbers n<index>, indexing parts and part_numbers.
+// Each cluster gets its own subgraph; numbers that aren't parts are drawn
+// dashed outside of any cluster.
+pub fn write_dot<W: Write>(analysis: &Analysis, out: &mut W) -> io::Result<()> {
+    let components = components(analysis)?;
+    writeln!(out, "graph schematic {{")?;
+    for (index, component) in components.iter().enumerate() {
+        writeln!(out, "    subgraph cluster_{} {{", index)?;
+        writeln!(out, "        label=\"sum {}\";", component.sum)?;
+        for &symbol in &component.symbols {
+            write_symbol_node(analysis, symbol, "        ", out)?;
+        }
+        for &number in &component.numbers {
+            let part_number = &analysis.part_numbers[number];
+            writeln!(
+                out,
+                "        n{} [label=\"{} ({},{})\"];",
+                number, part_number.number, part_number.x, part_number.y
+            )?;
+        }
+        writeln!(out, "    }}")?;
+    }
+    for (symbol, numbers) in analysis.adjacent_numbers.iter().enumerate() {
+        if numbers.is_empty() {
+            write_symbol_node(analysis, symbol, "    ", out)?;
+        }
+    }
+    for (number, part_number) in analysis.part_numbers.iter().enumerate() {
+        if !part_number.real_part {
+            writeln!(
+                out,
+                "    n{} [label=\"{} ({},{})\", style=dashed];",
+                number, part_number.number, part_number.x, part_number.y
+            )?;
+        }
+    }
+    for (symbol, numbers) in analysis.adjacent_numbers.iter().enumerate() {
+        for number in numbers {
+            writeln!(out, "    s{} -- n{};", symbol, number)?;
+        }
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn write_symbol_node<W: Write>(
+    analysis: &Analysis,
+    symbol: usize,
+    indent: &str,
+    out: &mut W,
+) -> io::Result<()> {
+    let part = &analysis.parts[symbol];
+    writeln!(
+        out,
+        "{}s{} [label=\"{} ({},{})\", shape=box];",
+        indent,
+        symbol,
+        escape(&part.symbol),
+        part.x,
+        part.y
+    )
+}
+
+pub fn write_json<W: Write>(analysis: &Analysis, out: &mut W) -> io::Result<()> {
+    let components = components(analysis)?;
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"symbols\": [")?;
+    for (index, part) in analysis.parts.iter().enumerate() {
+        writeln!(
+            out,
+            "    {{\"id\": {}, \"symbol\": \"{}\", \"x\": {}, \"y\": {}}}{}",
+            index,
+            escape(&part.symbol),
+            part.x,
+            part.y,
+            comma(index, analysis.parts.len())
+        )?;
+    }
+    writeln!(out, "  ],")?;
+    writeln!(out, "  \"numbers\": [")?;
+    for (index, part_number) in analysis.part_numbers.iter().enumerate() {
+        writeln!(
+            out,
+            "    {{\"id\": {}, \"number\": {}, \"x\": {}, \"y\": {}, \"width\": {}, \"real_part\": {}}}{}",
+            index,
+            part_number.number,
+            part_number.x,
+            part_number.y,
+            part_number.width,
+            part_number.real_part,
+            comma(index, analysis.part_numbers.len())
+        )?;
+    }
+    writeln!(out, "  ],")?;
+    let edges: Vec<String> = analysis
+        .adjacent_numbers
+        .iter()
+        .enumerate()
+        .flat_map(|(symbol, numbers)| {
+            numbers
+                .iter()
+                .map(move |number| format!("{{\"symbol\": {}, \"number\": {}}}", symbol, number))
+        })
+        .collect();
+    writeln!(out, "  \"edges\": [")?;
+    for (index, edge) in edges.iter().enumerate() {
+        writeln!(out, "    {}{}", edge, comma(index, edges.len()))?;
+    }
+    writeln!(out, "  ],")?;
+    writeln!(out, "  \"components\": [")?;
+    for (index, component) in components.iter().enumerate() {
+        writeln!(
+            out,
+            "    {{\"symbols\": {:?}, \"numbers\": {:?}, \"sum\": {}}}{}",
+            component.symbols,
+            component.numbers,
+            component.sum,
+            comma(index, components.len())
+        )?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+pub fn write_components<W: Write>(analysis: &Analysis, out: &mut W) -> io::Result<()> {
+    for (index, component) in components(analysis)?.iter().enumerate() {
+        let symbols: Vec<String> = component
+            .symbols
+            .iter()
+            .map(|&symbol| {
+                let part = &analysis.parts[symbol];
+                format!("'{}' at {},{}", part.symbol, part.x, part.y)
+            })
+            .collect();
+        let numbers: Vec<String> = component
+            .numbers
+            .iter()
+            .map(|&number| analysis.part_numbers[number].number.to_string())
+            .collect();
+        writeln!(
+            out,
+            "cluster {}: sum {}, symbols: {}; numbers: {}",
+            index + 1,
+            component.sum,
+            symbols.join(", "),
+            numbers.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+pub fn comma(index: usize, len: usize) -> &'static str {
+    if index + 1 < len {
+        ","
+    } else {
+        ""
+    }
+}
+
+// Quotes and backslashes are the only characters that need escaping in both
+// DOT and JSON strings; validation has already rejected control characters.
+pub fn escape(symbol: &str) -> String {
+    let mut escaped = String::with_capacity(symbol.len());
+    for c in symbol.chars() {
+        if c == '"' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{analyze, analyze_with, SAMPLE};
+    use crate::{Options, Tokenizer};
+
+    #[test]
+    fn test_components_sample() {
+        let analysis = analyze(SAMPLE);
+        let components = components(&analysis).unwrap();
+        // Every symbol in the sample touches its own numbers only.
+        assert_eq!(analysis.parts.len(), components.len());
+        assert_eq!(
+            analysis.sum,
+            components
+                .iter()
+                .map(|component| component.sum)
+                .sum::<i64>()
+        );
+        assert_eq!(vec![0, 2], components[0].numbers);
+        assert_eq!(467 + 35, components[0].sum);
+    }
+
+    #[test]
+    fn test_components_join_through_shared_numbers() {
+        // 12 touches both symbols, so all three numbers form one cluster;
+        // 9 touches nothing.
+        let analysis = analyze("1.....\n.#12+.\n....3.\n9.....\n");
+        assert_eq!(
+            vec![Component {
+                symbols: vec![0, 1],
+                numbers: vec![0, 1, 2],
+                sum: 16,
+            }],
+            components(&analysis).unwrap()
+        );
+        let mut out = Vec::new();
+        write_components(&analysis, &mut out).unwrap();
+        assert_eq!(
+            "cluster 1: sum 16, symbols: '#' at 1,1, '+' at 4,1; numbers: 1, 12, 3\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let analysis = analyze("1.\"\n.*.\n..5\n7..\n");
+        let mut out = Vec::new();
+        write_dot(&analysis, &mut out).unwrap();
+        let dot = String::from_utf8(out).unwrap();
+        assert!(dot.starts_with("graph schematic {\n    subgraph cluster_0 {\n"));
+        assert!(dot.contains("        label=\"sum 6\";\n"));
+        assert!(dot.contains("        s1 [label=\"* (1,1)\", shape=box];\n"));
+        assert!(dot.contains("    s0 [label=\"\\\" (2,0)\", shape=box];\n"));
+        assert!(dot.contains("    n2 [label=\"7 (0,3)\", style=dashed];\n"));
+        assert!(dot.contains("    s1 -- n0;\n    s1 -- n1;\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_write_json() {
+        let analysis = analyze("1.\"\n.*.\n..5\n7..\n");
+        let mut out = Vec::new();
+        write_json(&analysis, &mut out).unwrap();
+        assert_eq!(
+            concat!(
+                "{\n",
+                "  \"symbols\": [\n",
+                "    {\"id\": 0, \"symbol\": \"\\\"\", \"x\": 2, \"y\": 0},\n",
+                "    {\"id\": 1, \"symbol\": \"*\", \"x\": 1, \"y\": 1}\n",
+                "  ],\n",
+                "  \"numbers\": [\n",
+                "    {\"id\": 0, \"number\": 1, \"x\": 0, \"y\": 0, \"width\": 1, \"real_part\": true},\n",
+                "    {\"id\": 1, \"number\": 5, \"x\": 2, \"y\": 2, \"width\": 1, \"real_part\": true},\n",
+                "    {\"id\": 2, \"number\": 7, \"x\": 0, \"y\": 3, \"width\": 1, \"real_part\": false}\n",
+                "  ],\n",
+                "  \"edges\": [\n",
+                "    {\"symbol\": 1, \"number\": 0},\n",
+                "    {\"symbol\": 1, \"number\": 1}\n",
+                "  ],\n",
+                "  \"components\": [\n",
+                "    {\"symbols\": [1], \"numbers\": [0, 1], \"sum\": 6}\n",
+                "  ]\n",
+                "}\n",
+            ),
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_components_rejects_overflowing_sums() {
+        // The overall sum fits, but not the sum of the two numbers at '#'.
+        let input = "-9000000000000000000*\n.\n9000000000000000000#9000000000000000000\n";
+        let options = Options {
+            tokenizer: Tokenizer {
+                signed: true,
+                ..Tokenizer::default()
+            },
+            ..Options::default()
+        };
+        let analysis = analyze_with(input, &options);
+        assert_eq!(9000000000000000000, analysis.sum);
+        let err = components(&analysis).unwrap_err();
+        assert_eq!(
+            "line 3, column 21: part number takes the sum past an i64",
+            err.to_string()
+        );
+        assert!(write_components(&analysis, &mut Vec::new()).is_err());
+    }
+}
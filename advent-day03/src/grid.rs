@@ -0,0 +1,297 @@
+// A 2D grid, either dense and row-major or sparse and keyed by coordinate.
+// Coordinates are (x, y) with x growing to the right and y growing down;
+// anything outside the grid is simply skipped by the neighbor iterators
+// unless they're asked to wrap.
+
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    fill: T,
+    cells: Storage<T>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Storage<T> {
+    Dense(Vec<T>),
+    // Only the cells that have been written; the rest read as `fill`.
+    Sparse(HashMap<(usize, usize), T>),
+}
+
+// Which cells count as touching one another.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Neighborhood {
+    Orthogonal,
+    // Every cell within this many steps, diagonals included.
+    Chebyshev(usize),
+}
+
+impl Neighborhood {
+    // Row-major, like SURROUNDING, so neighbors come out in the same order
+    // whichever neighborhood is used.
+    pub fn offsets(&self) -> Vec<(isize, isize)> {
+        let radius = match self {
+            Neighborhood::Orthogonal => 1,
+            Neighborhood::Chebyshev(radius) => *radius as isize,
+        };
+        let mut offsets = Vec::new();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let diagonal = dx != 0 && dy != 0;
+                if (dx, dy) == (0, 0) || (diagonal && *self == Neighborhood::Orthogonal) {
+                    continue;
+                }
+                offsets.push((dx, dy));
+            }
+        }
+        offsets
+    }
+}
+
+const ORTHOGONAL: [(isize, isize); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+const SURROUNDING: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+impl<T: Clone> Grid<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Grid<T> {
+        Grid {
+            width,
+            height,
+            cells: Storage::Dense(vec![fill.clone(); width * height]),
+            fill,
+        }
+    }
+
+    pub fn sparse(width: usize, height: usize, fill: T) -> Grid<T> {
+        Grid {
+            width,
+            height,
+            cells: Storage::Sparse(HashMap::new()),
+            fill,
+        }
+    }
+
+    // On a sparse grid this stores the cell, so only call it to write.
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        match &mut self.cells {
+            Storage::Dense(cells) => Some(&mut cells[y * self.width + x]),
+            Storage::Sparse(cells) => {
+                Some(cells.entry((x, y)).or_insert_with(|| self.fill.clone()))
+            }
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[cfg(test)]
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.cells, Storage::Sparse(_))
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        match &self.cells {
+            Storage::Dense(cells) => Some(&cells[y * self.width + x]),
+            Storage::Sparse(cells) => Some(cells.get(&(x, y)).unwrap_or(&self.fill)),
+        }
+    }
+
+    // Day 3 itself always wants all eight neighbors; this is for the grid
+    // puzzles that only step orthogonally.
+    #[allow(dead_code)]
+    pub fn neighbors4(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.neighbors(x, y, &ORTHOGONAL, false)
+    }
+
+    pub fn neighbors8(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.neighbors(x, y, &SURROUNDING, false)
+    }
+
+    // The cells at each offset from (x, y), as given by
+    // Neighborhood::offsets. With `wrap` the grid is a torus and nothing is
+    // skipped, so on a small grid a cell can come up more than once.
+    pub fn neighbors<'a>(
+        &'a self,
+        x: usize,
+        y: usize,
+        offsets: &'a [(isize, isize)],
+        wrap: bool,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let (x, y) = (x as isize, y as isize);
+        offsets.iter().filter_map(move |&(dx, dy)| {
+            let (x, y) = (x + dx, y + dy);
+            if wrap && self.width > 0 && self.height > 0 {
+                Some((
+                    x.rem_euclid(self.width as isize) as usize,
+                    y.rem_euclid(self.height as isize) as usize,
+                ))
+            } else {
+                self.checked(x, y)
+            }
+        })
+    }
+
+    // Every in-bounds cell touching the run of `length` cells starting at
+    // (x, y) and extending right, diagonals included, excluding the run
+    // itself. Walks the ring clockwise from the top-left corner.
+    pub fn span_neighbors(
+        &self,
+        x: usize,
+        y: usize,
+        length: usize,
+    ) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (x, y, length) = (x as isize, y as isize, length as isize);
+        let top = (-1..=length).map(move |dx| (x + dx, y - 1));
+        let right = std::iter::once((x + length, y));
+        let bottom = (-1..=length).rev().map(move |dx| (x + dx, y + 1));
+        let left = std::iter::once((x - 1, y));
+        top.chain(right)
+            .chain(bottom)
+            .chain(left)
+            .filter_map(move |(cx, cy)| self.checked(cx, cy))
+    }
+
+    fn checked(&self, x: isize, y: isize) -> Option<(usize, usize)> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            Some((x as usize, y as usize))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_checks_bounds() {
+        let mut grid = Grid::new(3, 2, 0);
+        *grid.get_mut(2, 1).unwrap() = 7;
+        assert_eq!(Some(&7), grid.get(2, 1));
+        assert_eq!(Some(&0), grid.get(0, 0));
+        assert_eq!(None, grid.get(3, 0));
+        assert_eq!(None, grid.get(0, 2));
+        assert!(grid.get_mut(3, 1).is_none());
+        assert_eq!((3, 2), (grid.width(), grid.height()));
+    }
+
+    #[test]
+    fn test_sparse_get_checks_bounds() {
+        let mut grid = Grid::sparse(3, 2, 0);
+        *grid.get_mut(2, 1).unwrap() = 7;
+        assert_eq!(Some(&7), grid.get(2, 1));
+        assert_eq!(Some(&0), grid.get(0, 0));
+        assert_eq!(None, grid.get(3, 0));
+        assert!(grid.get_mut(0, 2).is_none());
+        assert!(grid.is_sparse());
+        assert!(!Grid::new(1, 1, 0).is_sparse());
+    }
+
+    #[test]
+    fn test_neighborhood_offsets() {
+        assert_eq!(ORTHOGONAL.to_vec(), Neighborhood::Orthogonal.offsets());
+        assert_eq!(SURROUNDING.to_vec(), Neighborhood::Chebyshev(1).offsets());
+        assert_eq!(24, Neighborhood::Chebyshev(2).offsets().len());
+    }
+
+    #[test]
+    fn test_neighbors4() {
+        let grid = Grid::new(3, 3, ());
+        assert_eq!(
+            vec![(1, 0), (0, 1), (2, 1), (1, 2)],
+            grid.neighbors4(1, 1).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(1, 0), (0, 1)],
+            grid.neighbors4(0, 0).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_neighbors8() {
+        let grid = Grid::new(3, 3, ());
+        assert_eq!(8, grid.neighbors8(1, 1).count());
+        assert_eq!(
+            vec![(1, 1), (2, 1), (1, 2)],
+            grid.neighbors8(2, 2)
+                .filter(|&(x, y)| x >= 1 && y >= 1)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(3, grid.neighbors8(0, 0).count());
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let grid = Grid::new(4, 3, ());
+        let orthogonal = Neighborhood::Orthogonal.offsets();
+        assert_eq!(
+            vec![(1, 0), (0, 1), (2, 1), (1, 2)],
+            grid.neighbors(1, 1, &orthogonal, false).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(1, 0), (0, 1)],
+            grid.neighbors(0, 0, &orthogonal, false).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(0, 2), (3, 0), (1, 0), (0, 1)],
+            grid.neighbors(0, 0, &orthogonal, true).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(1, 0)],
+            grid.neighbors(3, 2, &[(2, 1)], true).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(0, 1)],
+            grid.neighbors(0, 1, &[(-8, 6)], true).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_span_neighbors() {
+        let grid = Grid::new(6, 3, ());
+        assert_eq!(
+            vec![
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (3, 0),
+                (3, 1),
+                (3, 2),
+                (2, 2),
+                (1, 2),
+                (0, 2),
+                (0, 1)
+            ],
+            grid.span_neighbors(1, 1, 2).collect::<Vec<_>>()
+        );
+        // Clipped at the top-left corner.
+        assert_eq!(
+            vec![(3, 0), (3, 1), (2, 1), (1, 1), (0, 1)],
+            grid.span_neighbors(0, 0, 3).collect::<Vec<_>>()
+        );
+    }
+}
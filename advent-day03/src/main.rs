@@ -1,182 +1,797 @@
+use std::env;
+use std::fmt;
 use std::io::{self, BufRead, Read};
+use std::process;
+
+mod edit;
+mod graph;
+mod grid;
+mod render;
+#[cfg(test)]
+mod rng;
+mod stream;
+mod symbols;
+
+use grid::{Grid, Neighborhood};
+use render::Format;
 
 #[derive(Debug, PartialEq)]
 struct PartNumber {
     x: u32,
     width: u32,
     y: u32,
-    number: u32,
+    number: i64,
     real_part: bool,
 }
 
+// Symbols are usually a single char, but a multi-char token from the
+// Tokenizer is one Part covering `width` cells.
 #[derive(Debug, PartialEq)]
 struct Part {
     x: u32,
     y: u32,
+    width: u32,
+    symbol: String,
+}
+
+impl Part {
+    fn is(&self, symbol: char) -> bool {
+        self.symbol.chars().eq([symbol])
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Tokenizer {
+    signed: bool,
+    // Longest first, so the longest token at a position wins.
+    tokens: Vec<Vec<char>>,
+}
+
+impl Tokenizer {
+    fn add_tokens(&mut self, spec: &str) -> Result<(), String> {
+        for token in spec.split(',') {
+            let chars: Vec<char> = token.chars().collect();
+            let valid = chars.len() > 1
+                && chars.iter().all(|c| {
+                    *c != '.' && !c.is_ascii_digit() && !c.is_whitespace() && !c.is_control()
+                });
+            if !valid {
+                return Err(format!("invalid token {:?}", token));
+            }
+            if !self.tokens.contains(&chars) {
+                self.tokens.push(chars);
+            }
+        }
+        self.tokens
+            .sort_by_key(|token| std::cmp::Reverse(token.len()));
+        Ok(())
+    }
+
+    fn token_at(&self, chars: &[char]) -> Option<usize> {
+        self.tokens
+            .iter()
+            .find(|token| chars.starts_with(token))
+            .map(|token| token.len())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Cell {
+    Empty,
+    Number(usize),
+    Symbol(usize),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum NeighborCount {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Aggregate {
+    Product,
+    Sum,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+enum Storage {
+    #[default]
+    Auto,
+    Dense,
+    Sparse,
+}
+
+impl Storage {
+    fn from_name(name: &str) -> Result<Storage, String> {
+        match name {
+            "auto" => Ok(Storage::Auto),
+            "dense" => Ok(Storage::Dense),
+            "sparse" => Ok(Storage::Sparse),
+            _ => Err(format!("unknown storage {:?}", name)),
+        }
+    }
+
+    // A sparse cell costs several times a dense one, so it only pays off
+    // when almost everything is '.'.
+    fn sparse_for(&self, occupied: usize, width: usize, height: usize) -> bool {
+        match self {
+            Storage::Auto => occupied * 20 < width * height,
+            Storage::Dense => false,
+            Storage::Sparse => true,
+        }
+    }
+}
+
+// Which cells count as touching a symbol, for both part and gear detection.
+// With `wrap` the schematic is a torus: the left edge touches the right and
+// the top touches the bottom.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Adjacency {
+    neighborhood: Neighborhood,
+    wrap: bool,
+}
+
+impl Default for Adjacency {
+    fn default() -> Adjacency {
+        Adjacency {
+            neighborhood: Neighborhood::Chebyshev(1),
+            wrap: false,
+        }
+    }
+}
+
+impl Adjacency {
+    fn from_name(name: &str) -> Result<Neighborhood, String> {
+        match name {
+            "orthogonal" => Ok(Neighborhood::Orthogonal),
+            "surrounding" => Ok(Neighborhood::Chebyshev(1)),
+            _ => match name.strip_prefix("radius:").map(str::parse::<usize>) {
+                Some(Ok(radius)) if radius > 0 => Ok(Neighborhood::Chebyshev(radius)),
+                _ => Err(format!("unknown adjacency {:?}", name)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct GearRule {
     symbol: char,
+    neighbors: NeighborCount,
+    aggregate: Aggregate,
+}
+
+impl Default for GearRule {
+    fn default() -> GearRule {
+        GearRule {
+            symbol: '*',
+            neighbors: NeighborCount::Exactly(2),
+            aggregate: Aggregate::Product,
+        }
+    }
+}
+
+impl GearRule {
+    fn accepts(&self, count: usize) -> bool {
+        match self.neighbors {
+            NeighborCount::Exactly(n) => count == n,
+            NeighborCount::AtLeast(n) => count >= n,
+        }
+    }
+
+    fn overfull(&self, count: usize) -> bool {
+        match self.neighbors {
+            NeighborCount::Exactly(n) => count > n,
+            NeighborCount::AtLeast(_) => false,
+        }
+    }
+
+    // None if the ratio doesn't fit in an i64, which a handful of large
+    // numbers around a gear is enough for.
+    fn ratio(&self, numbers: &[i64]) -> Option<i64> {
+        match self.aggregate {
+            Aggregate::Product => numbers
+                .iter()
+                .try_fold(1i64, |ratio, &number| ratio.checked_mul(number)),
+            Aggregate::Sum => numbers
+                .iter()
+                .try_fold(0i64, |ratio, &number| ratio.checked_add(number)),
+        }
+    }
+
+    // Adds the ratio of every gear to `total`, or returns the position of
+    // the first gear whose ratio takes it past an i64.
+    fn add_ratios(&self, total: i64, gears: &[Gear]) -> Result<i64, (u32, u32)> {
+        gears.iter().try_fold(total, |total, gear| {
+            self.ratio(&gear.numbers)
+                .and_then(|ratio| total.checked_add(ratio))
+                .ok_or((gear.x, gear.y))
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Gear {
+    x: u32,
+    y: u32,
+    numbers: Vec<i64>,
+}
+
+// Everything the command line can change about how a schematic is read.
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Options {
+    rule: GearRule,
+    storage: Storage,
+    adjacency: Adjacency,
+    tokenizer: Tokenizer,
+}
+
+#[derive(Debug, PartialEq)]
+struct Analysis {
+    sum: i64,
+    gear_ratios: i64,
+    gears: Vec<Gear>,
+    overfull_gears: Vec<Gear>,
+    part_numbers: Vec<PartNumber>,
+    parts: Vec<Part>,
+    // The symbol-number graph: the indices into `part_numbers` of the
+    // numbers touching each of `parts`, in neighbor order.
+    adjacent_numbers: Vec<Vec<usize>>,
+    grid: Grid<Cell>,
+    // The input line each row came from, as blank lines aren't rows.
+    lines: Vec<usize>,
+}
+
+impl Analysis {
+    // For the per-symbol and per-component sums, which can overflow even
+    // though the overall sum doesn't.
+    fn sum_overflow(&self, part_number: &PartNumber) -> SchematicError {
+        SchematicError::at(
+            self.lines[part_number.y as usize],
+            sum_overflow(part_number),
+        )
+    }
+}
+
+// Lines and columns are 1-based and count input lines and chars, so they
+// point at the offending character in an editor.
+#[derive(Debug, PartialEq)]
+struct SchematicError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl SchematicError {
+    fn at(line: usize, (column, message): (usize, String)) -> SchematicError {
+        SchematicError {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+// The column and message for a gear or part number that takes a total past
+// an i64, to be placed on its line like validate_line's errors.
+fn ratio_overflow((x, y): (u32, u32)) -> (usize, String) {
+    let message = format!("gear at {},{} takes the gear ratios past an i64", x, y);
+    (x as usize + 1, message)
+}
+
+fn sum_overflow(part_number: &PartNumber) -> (usize, String) {
+    let message = "part number takes the sum past an i64".to_string();
+    (part_number.x as usize + 1, message)
+}
+
+// So that the writers can fail on a sum that overflows as they go.
+impl From<SchematicError> for io::Error {
+    fn from(err: SchematicError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+    }
+}
+
+impl fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
 }
 
 fn main() {
-    let (sum, gear_ratios) = process_input(io::stdin().lock());
-    println!("sum: {}, gear ratios: {}", sum, gear_ratios);
+    let mut options = Options::default();
+    let mut render_format = None;
+    let mut graph_format = None;
+    let mut breakdown_format = None;
+    let mut streaming = false;
+    let mut show_components = false;
+    let mut edits = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stream" => {
+                streaming = true;
+                continue;
+            }
+            "--components" => {
+                show_components = true;
+                continue;
+            }
+            "--wrap" => {
+                options.adjacency.wrap = true;
+                continue;
+            }
+            "--signed" => {
+                options.tokenizer.signed = true;
+                continue;
+            }
+            _ => {}
+        }
+        let value = args.next().unwrap_or_else(|| {
+            eprintln!("error: {} requires a value", arg);
+            process::exit(2);
+        });
+        let result = match arg.as_str() {
+            "--render" => Format::from_name(&value).map(|format| render_format = Some(format)),
+            "--storage" => Storage::from_name(&value).map(|value| options.storage = value),
+            "--tokens" => options.tokenizer.add_tokens(&value),
+            "--set" => edit::parse_edit(&value).map(|edit| edits.push(edit)),
+            "--adjacency" => {
+                Adjacency::from_name(&value).map(|value| options.adjacency.neighborhood = value)
+            }
+            "--graph" => match value.as_str() {
+                "dot" | "json" => {
+                    graph_format = Some(value);
+                    Ok(())
+                }
+                _ => Err(format!("unknown graph format {:?}", value)),
+            },
+            "--by-symbol" => match value.as_str() {
+                "text" | "json" => {
+                    breakdown_format = Some(value);
+                    Ok(())
+                }
+                _ => Err(format!("unknown breakdown format {:?}", value)),
+            },
+            _ => apply_gear_flag(&mut options.rule, &arg, &value),
+        };
+        if let Err(err) = result {
+            eprintln!("error: {}", err);
+            process::exit(2);
+        }
+    }
+    let rule = options.rule;
+    if streaming {
+        if render_format.is_some()
+            || graph_format.is_some()
+            || breakdown_format.is_some()
+            || show_components
+            || !edits.is_empty()
+        {
+            eprintln!("error: --stream only reports the totals of each row and overall");
+            process::exit(2);
+        }
+        if options.adjacency != Adjacency::default() || options.tokenizer != Tokenizer::default() {
+            eprintln!("error: --stream only supports the default adjacency and tokenizer");
+            process::exit(2);
+        }
+        // Each row is reported as soon as it settles, so a long schematic
+        // shows progress before its overall totals.
+        let totals = stream::stream_input(io::stdin().lock(), &rule, |summary| {
+            println!(
+                "row {}: sum: {}, gear ratios: {}",
+                summary.y, summary.sum, summary.gear_ratios
+            );
+            for gear in &summary.overfull_gears {
+                report_overfull_gear(&rule, gear);
+            }
+        })
+        .unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        });
+        println!("sum: {}, gear ratios: {}", totals.sum, totals.gear_ratios);
+        return;
+    }
+    if !edits.is_empty()
+        && (options.adjacency != Adjacency::default() || options.tokenizer != Tokenizer::default())
+    {
+        eprintln!("error: --set only supports the default adjacency and tokenizer");
+        process::exit(2);
+    }
+    let mut input = String::new();
+    if let Err(err) = io::stdin().read_to_string(&mut input) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+    let analysis = process_input(input.as_bytes(), &options).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    });
+    for gear in &analysis.overfull_gears {
+        report_overfull_gear(&rule, gear);
+    }
+    let mut stdout = io::stdout();
+    match graph_format.as_deref() {
+        Some("dot") => return exit_on_error(graph::write_dot(&analysis, &mut stdout)),
+        Some(_) => return exit_on_error(graph::write_json(&analysis, &mut stdout)),
+        None => {}
+    }
+    if breakdown_format.as_deref() == Some("json") {
+        return exit_on_error(symbols::write_json(&analysis, &mut stdout));
+    }
+    if let Some(format) = render_format {
+        exit_on_error(render::render(
+            &input,
+            &analysis,
+            &rule,
+            format,
+            &mut stdout,
+        ));
+        if format == Format::Html {
+            return;
+        }
+    }
+    if show_components {
+        exit_on_error(graph::write_components(&analysis, &mut stdout));
+    }
+    if breakdown_format.is_some() {
+        exit_on_error(symbols::write_text(&analysis, &mut stdout));
+    }
+    println!(
+        "sum: {}, gear ratios: {}",
+        analysis.sum, analysis.gear_ratios
+    );
+    if edits.is_empty() {
+        return;
+    }
+    // The edits are applied one after another, each reporting the totals
+    // it leaves behind.
+    let mut schematic = edit::Schematic::parse(&input, rule).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    });
+    for (x, y, c) in edits {
+        if let Err(err) = schematic.set(x, y, c) {
+            eprintln!("error: --set {},{},{}: {}", x, y, c, err);
+            process::exit(1);
+        }
+        println!(
+            "set {},{} to '{}': sum: {}, gear ratios: {}",
+            x,
+            y,
+            c,
+            schematic.sum(),
+            schematic.gear_ratios()
+        );
+    }
+    for gear in schematic.overfull_gears() {
+        report_overfull_gear(&rule, gear);
+    }
+}
+
+fn exit_on_error(result: io::Result<()>) {
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
 }
 
-fn process_input<R: Read>(reader: R) -> (u32, u32) {
+fn report_overfull_gear(rule: &GearRule, gear: &Gear) {
+    eprintln!(
+        "gear '{}' at {},{} has {} adjacent numbers: {}",
+        rule.symbol,
+        gear.x,
+        gear.y,
+        gear.numbers.len(),
+        gear.numbers
+            .iter()
+            .map(|number| number.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+}
+
+fn apply_gear_flag(rule: &mut GearRule, flag: &str, value: &str) -> Result<(), String> {
+    match flag {
+        "--gear-symbol" => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(symbol), None) if symbol != '.' && !symbol.is_ascii_digit() => {
+                    rule.symbol = symbol
+                }
+                _ => return Err(format!("invalid gear symbol {:?}", value)),
+            }
+        }
+        "--gear-count" => {
+            let (count, at_least) = match value.strip_suffix('+') {
+                Some(count) => (count, true),
+                None => (value, false),
+            };
+            let count = count
+                .parse::<usize>()
+                .map_err(|err| format!("invalid gear count {:?}: {}", value, err))?;
+            rule.neighbors = if at_least {
+                NeighborCount::AtLeast(count)
+            } else {
+                NeighborCount::Exactly(count)
+            };
+        }
+        "--gear-aggregate" => {
+            rule.aggregate = match value {
+                "product" => Aggregate::Product,
+                "sum" => Aggregate::Sum,
+                _ => return Err(format!("unknown gear aggregate {:?}", value)),
+            }
+        }
+        _ => return Err(format!("unknown argument: {}", flag)),
+    }
+    Ok(())
+}
+
+// Rows may be ragged: the grid is as wide as the longest row and the cells
+// past the end of a shorter row are empty. Columns are counted in chars, so
+// a multibyte symbol takes up a single cell.
+fn process_input<R: Read>(reader: R, options: &Options) -> Result<Analysis, SchematicError> {
+    let Options {
+        rule,
+        storage,
+        adjacency,
+        tokenizer,
+    } = options;
     let mut x_len = 0;
     let mut y_len = 0;
     let mut parts: Vec<Part> = Vec::new();
     let mut part_numbers: Vec<PartNumber> = Vec::new();
+    // The input line each row came from, as blank lines aren't rows.
+    let mut lines = Vec::new();
     let buffered = io::BufReader::new(reader);
-    for line_result in buffered.lines() {
-        let line = line_result.unwrap();
+    for (index, line_result) in buffered.lines().enumerate() {
+        let line = line_result.map_err(|err| SchematicError {
+            line: index + 1,
+            column: 1,
+            message: err.to_string(),
+        })?;
         if line.trim().is_empty() {
             continue;
         }
-        process_line(&mut part_numbers, &mut parts, &line, y_len);
+        validate_line(&line).map_err(|(column, message)| SchematicError {
+            line: index + 1,
+            column,
+            message,
+        })?;
+        tokenize_line(&mut part_numbers, &mut parts, &line, y_len, tokenizer);
+        lines.push(index + 1);
         y_len += 1;
-        if x_len == 0 {
-            x_len = line.len() as u32;
-        }
-    }
-    let mut matrix: Vec<Vec<Option<usize>>> = Vec::new();
-    while matrix.len() < y_len as usize {
-        let mut row = Vec::new();
-        while row.len() < x_len as usize {
-            row.push(None);
-        }
-        matrix.push(row);
+        x_len = x_len.max(line.chars().count() as u32);
     }
+    let (width, height) = (x_len as usize, y_len as usize);
+    let occupied = parts.iter().map(|part| part.width as usize).sum::<usize>()
+        + part_numbers
+            .iter()
+            .map(|part_number| part_number.width as usize)
+            .sum::<usize>();
+    let mut grid = if storage.sparse_for(occupied, width, height) {
+        Grid::sparse(width, height, Cell::Empty)
+    } else {
+        Grid::new(width, height, Cell::Empty)
+    };
     for (index, part_number) in part_numbers.iter().enumerate() {
         for x in part_number.x..(part_number.x + part_number.width) {
-            matrix[part_number.y as usize][x as usize] = Some(index as usize);
+            *grid.get_mut(x as usize, part_number.y as usize).unwrap() = Cell::Number(index);
         }
     }
-    let mut gear_parts_sets: Vec<Vec<usize>> = Vec::new();
-    for part in parts {
-        let mut gear_parts_indices: Vec<usize> = Vec::new();
-
-        let x = part.x;
-        let y = part.y;
-        for x_offset in -1..=1 {
-            for y_offset in -1..=1 {
-                if x_offset == 0 && y_offset == 0 {
-                    continue;
-                }
-                let mut current_x = x as i32;
-                let mut current_y = y as i32;
-
-                current_x += x_offset;
-                current_y += y_offset;
-                if current_x < 0 || current_y < 0 {
-                    continue;
-                }
-                let current_x = current_x as usize;
-                let current_y = current_y as usize;
-                if current_x >= matrix[0].len() || current_y >= matrix.len() {
-                    continue;
-                }
-                if let Some(index) = matrix[current_y][current_x] {
-                    part_numbers[index as usize].real_part = true;
-                    if part.symbol == '*' && !gear_parts_indices.contains(&index) {
-                        gear_parts_indices.push(index);
+    for (index, part) in parts.iter().enumerate() {
+        for x in part.x..(part.x + part.width) {
+            *grid.get_mut(x as usize, part.y as usize).unwrap() = Cell::Symbol(index);
+        }
+    }
+    let offsets = adjacency.neighborhood.offsets();
+    let mut adjacent_numbers: Vec<Vec<usize>> = Vec::with_capacity(parts.len());
+    for part in &parts {
+        let mut indices: Vec<usize> = Vec::new();
+        for x in part.x..(part.x + part.width) {
+            let neighbors = grid.neighbors(x as usize, part.y as usize, &offsets, adjacency.wrap);
+            for (nx, ny) in neighbors {
+                if let Some(&Cell::Number(index)) = grid.get(nx, ny) {
+                    if !indices.contains(&index) {
+                        indices.push(index);
                     }
                 }
             }
         }
-        if !gear_parts_indices.is_empty() {
-            gear_parts_sets.push(gear_parts_indices);
+        for &index in &indices {
+            part_numbers[index].real_part = true;
+        }
+        adjacent_numbers.push(indices);
+    }
+    let mut gears = Vec::new();
+    let mut overfull_gears = Vec::new();
+    for (part, indices) in parts.iter().zip(&adjacent_numbers) {
+        if !part.is(rule.symbol) {
+            continue;
+        }
+        let gear = Gear {
+            x: part.x,
+            y: part.y,
+            numbers: indices
+                .iter()
+                .map(|&index| part_numbers[index].number)
+                .collect(),
+        };
+        if rule.accepts(gear.numbers.len()) {
+            gears.push(gear);
+        } else if rule.overfull(gear.numbers.len()) {
+            overfull_gears.push(gear);
         }
     }
-    let gear_ratios = gear_parts_sets
-        .iter()
-        .filter(|v| v.len() == 2)
-        .map(|v| part_numbers[v[0]].number * part_numbers[v[1]].number)
-        .sum::<u32>();
-    let mut sum = 0;
-    for part_number in part_numbers {
+    let gear_ratios = rule
+        .add_ratios(0, &gears)
+        .map_err(|gear| SchematicError::at(lines[gear.1 as usize], ratio_overflow(gear)))?;
+    let mut sum: i64 = 0;
+    for part_number in &part_numbers {
         if part_number.real_part {
-            sum += part_number.number;
+            sum = sum.checked_add(part_number.number).ok_or_else(|| {
+                SchematicError::at(lines[part_number.y as usize], sum_overflow(part_number))
+            })?;
         }
     }
-    (sum, gear_ratios)
+    Ok(Analysis {
+        sum,
+        gear_ratios,
+        gears,
+        overfull_gears,
+        part_numbers,
+        parts,
+        adjacent_numbers,
+        grid,
+        lines,
+    })
 }
 
-fn process_line(part_numbers: &mut Vec<PartNumber>, parts: &mut Vec<Part>, line: &str, y_pos: u32) {
-    let mut current_number = String::new();
-    let mut current_x = 0;
-    let mut current_width = 0;
-    for (x_pos, c) in line.chars().enumerate() {
-        if c == '.' {
-            if !current_number.is_empty() {
-                let part = PartNumber {
-                    x: current_x + 1 - current_width,
-                    real_part: false,
-                    width: current_width,
-                    y: y_pos,
-                    number: current_number.parse::<u32>().unwrap(),
-                };
-                part_numbers.push(part);
-                current_number.clear();
-                current_width = 0;
-            }
-        } else if c.is_digit(10) {
-            current_number.push(c);
-            current_width += 1;
-            current_x = x_pos as u32;
+// Returns the 1-based column and a description of the first character
+// process_line can't make sense of: control characters (tabs included, as
+// they'd shift every column after them) and numbers too large for an i64.
+fn validate_line(line: &str) -> Result<(), (usize, String)> {
+    let mut number: Option<(usize, i64)> = None;
+    for (column, c) in line.chars().enumerate() {
+        if c.is_control() {
+            return Err((column + 1, format!("unexpected control character {:?}", c)));
+        }
+        if c.is_ascii_digit() {
+            let (start, value) = number.unwrap_or((column, 0));
+            let value = value
+                .checked_mul(10)
+                .and_then(|value| value.checked_add(c as i64 - '0' as i64))
+                .ok_or_else(|| (start + 1, "part number does not fit in an i64".to_string()))?;
+            number = Some((start, value));
         } else {
-            if !current_number.is_empty() {
-                let part = PartNumber {
-                    x: current_x + 1 - current_width,
-                    real_part: false,
-                    width: current_width,
-                    y: y_pos,
-                    number: current_number.parse::<u32>().unwrap(),
-                };
-                part_numbers.push(part);
-                current_number.clear();
-                current_width = 0;
+            number = None;
+        }
+    }
+    Ok(())
+}
+
+fn process_line(part_numbers: &mut Vec<PartNumber>, parts: &mut Vec<Part>, line: &str, y_pos: u32) {
+    tokenize_line(part_numbers, parts, line, y_pos, &Tokenizer::default());
+}
+
+// With `signed`, a '-' directly before a digit is the number's sign rather
+// than a symbol, and the number's cells include it. Configured tokens are
+// tried before anything else at each position.
+fn tokenize_line(
+    part_numbers: &mut Vec<PartNumber>,
+    parts: &mut Vec<Part>,
+    line: &str,
+    y_pos: u32,
+    tokenizer: &Tokenizer,
+) {
+    let chars: Vec<char> = line.chars().collect();
+    let mut x = 0;
+    while x < chars.len() {
+        let c = chars[x];
+        if let Some(width) = tokenizer.token_at(&chars[x..]) {
+            parts.push(Part {
+                x: x as u32,
+                y: y_pos,
+                width: width as u32,
+                symbol: chars[x..x + width].iter().collect(),
+            });
+            x += width;
+            continue;
+        }
+        let sign = tokenizer.signed
+            && c == '-'
+            && chars.get(x + 1).is_some_and(|next| next.is_ascii_digit());
+        if c.is_ascii_digit() || sign {
+            let start = x;
+            x += 1;
+            while x < chars.len() && chars[x].is_ascii_digit() {
+                x += 1;
             }
-            let part = Part {
-                x: x_pos as u32,
+            let digits: String = chars[start..x].iter().collect();
+            part_numbers.push(PartNumber {
+                x: start as u32,
+                real_part: false,
+                width: (x - start) as u32,
                 y: y_pos,
-                symbol: c,
-            };
-            parts.push(part);
+                number: digits.parse::<i64>().unwrap(),
+            });
+            continue;
         }
-    }
-    if !current_number.is_empty() {
-        let part = PartNumber {
-            x: current_x + 1 - current_width,
-            real_part: false,
-            width: current_width,
-            y: y_pos,
-            number: current_number.parse::<u32>().unwrap(),
-        };
-        part_numbers.push(part);
+        if c != '.' {
+            parts.push(Part {
+                x: x as u32,
+                y: y_pos,
+                width: 1,
+                symbol: c.to_string(),
+            });
+        }
+        x += 1;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rng::Rng;
+
+    // The example schematic from the puzzle text.
+    pub const SAMPLE: &str = include_str!("../sample.input");
+
+    pub fn analyze(input: &str) -> Analysis {
+        analyze_with(input, &Options::default())
+    }
+
+    pub fn analyze_with(input: &str, options: &Options) -> Analysis {
+        process_input(input.as_bytes(), options).unwrap()
+    }
+
+    // Ragged rows of '.', digits and symbols, with `density` percent of the
+    // cells filled. Digit runs are capped at five digits, which keeps the
+    // sums and ratios far from overflowing.
+    pub fn random_schematic(rng: &mut Rng, density: u64) -> String {
+        let filled = b"0123456789012345*#+$*-/@";
+        let mut input = String::new();
+        for _ in 0..1 + rng.below(12) {
+            let mut digits = 0;
+            for _ in 0..1 + rng.below(30) {
+                let mut c = '.';
+                if rng.below(100) < density {
+                    c = filled[rng.below(filled.len() as u64) as usize] as char;
+                }
+                if c.is_ascii_digit() && digits == 5 {
+                    c = '.';
+                }
+                digits = if c.is_ascii_digit() { digits + 1 } else { 0 };
+                input.push(c);
+            }
+            input.push('\n');
+        }
+        input
+    }
 
     #[test]
     fn test_parse_line1() {
-        let mut expected = Vec::new();
-        expected.push(PartNumber {
-            x: 0,
-            real_part: false,
-            width: 3,
-            y: 0,
-            number: 467,
-        });
-        expected.push(PartNumber {
-            x: 5,
-            real_part: false,
-            width: 3,
-            y: 0,
-            number: 114,
-        });
+        let expected = vec![
+            PartNumber {
+                x: 0,
+                real_part: false,
+                width: 3,
+                y: 0,
+                number: 467,
+            },
+            PartNumber {
+                x: 5,
+                real_part: false,
+                width: 3,
+                y: 0,
+                number: 114,
+            },
+        ];
         let input = "467..114..";
         let mut parts: Vec<PartNumber> = Vec::new();
         process_line(&mut parts, &mut Vec::new(), input, 0);
@@ -185,14 +800,13 @@ mod tests {
 
     #[test]
     fn test_parse_line2() {
-        let mut expected = Vec::new();
-        expected.push(PartNumber {
+        let expected = vec![PartNumber {
             x: 0,
             real_part: false,
             width: 1,
             y: 0,
             number: 1,
-        });
+        }];
         let input = "1";
         let mut parts: Vec<PartNumber> = Vec::new();
         process_line(&mut parts, &mut Vec::new(), input, 0);
@@ -201,28 +815,29 @@ mod tests {
 
     #[test]
     fn test_parse_line3() {
-        let mut expected = Vec::new();
-        expected.push(PartNumber {
-            x: 0,
-            real_part: false,
-            width: 1,
-            y: 0,
-            number: 1,
-        });
-        expected.push(PartNumber {
-            x: 2,
-            real_part: false,
-            width: 1,
-            y: 0,
-            number: 1,
-        });
-        expected.push(PartNumber {
-            x: 4,
-            real_part: false,
-            width: 1,
-            y: 0,
-            number: 1,
-        });
+        let expected = vec![
+            PartNumber {
+                x: 0,
+                real_part: false,
+                width: 1,
+                y: 0,
+                number: 1,
+            },
+            PartNumber {
+                x: 2,
+                real_part: false,
+                width: 1,
+                y: 0,
+                number: 1,
+            },
+            PartNumber {
+                x: 4,
+                real_part: false,
+                width: 1,
+                y: 0,
+                number: 1,
+            },
+        ];
         let input = "1.1.1";
         let mut parts: Vec<PartNumber> = Vec::new();
         process_line(&mut parts, &mut Vec::new(), input, 0);
@@ -231,14 +846,13 @@ mod tests {
 
     #[test]
     fn test_parse_line4() {
-        let mut expected = Vec::new();
-        expected.push(PartNumber {
+        let expected = vec![PartNumber {
             x: 0,
             real_part: false,
             width: 5,
             y: 0,
             number: 12345,
-        });
+        }];
         let input = "12345";
         let mut parts: Vec<PartNumber> = Vec::new();
         process_line(&mut parts, &mut Vec::new(), input, 0);
@@ -247,14 +861,13 @@ mod tests {
 
     #[test]
     fn test_parse_line5() {
-        let mut expected = Vec::new();
-        expected.push(PartNumber {
+        let expected = vec![PartNumber {
             x: 0,
             real_part: false,
             width: 5,
             y: 0,
             number: 12345,
-        });
+        }];
         let input = "12345.";
         let mut parts: Vec<PartNumber> = Vec::new();
         process_line(&mut parts, &mut Vec::new(), input, 0);
@@ -268,12 +881,14 @@ mod tests {
         expected_parts.push(Part {
             x: 3,
             y: 0,
-            symbol: '$',
+            width: 1,
+            symbol: "$".to_string(),
         });
         expected_parts.push(Part {
             x: 5,
             y: 0,
-            symbol: '*',
+            width: 1,
+            symbol: "*".to_string(),
         });
         let input = "...$.*....";
         let mut part_numbers: Vec<PartNumber> = Vec::new();
@@ -290,7 +905,8 @@ mod tests {
         expected_parts.push(Part {
             x: 3,
             y: 4,
-            symbol: '*',
+            width: 1,
+            symbol: "*".to_string(),
         });
         expected_part_numbers.push(PartNumber {
             x: 0,
@@ -314,12 +930,14 @@ mod tests {
         expected_parts.push(Part {
             x: 3,
             y: 4,
-            symbol: '*',
+            width: 1,
+            symbol: "*".to_string(),
         });
         expected_parts.push(Part {
             x: 7,
             y: 4,
-            symbol: '*',
+            width: 1,
+            symbol: "*".to_string(),
         });
         expected_part_numbers.push(PartNumber {
             x: 0,
@@ -345,20 +963,8 @@ mod tests {
 
     #[test]
     fn test_process_input_advent_input_1() {
-        let input = r#"
-467..114..
-...*......
-..35..633.
-......#...
-617*......
-.....+.58.
-..592.....
-......755.
-...$.*....
-.664.598..
-        "#;
-        let result = process_input(input.as_bytes());
-        assert_eq!((4361, 467835), result);
+        let result = analyze(SAMPLE);
+        assert_eq!((4361, 467835), (result.sum, result.gear_ratios));
     }
 
     #[test]
@@ -368,7 +974,7 @@ mod tests {
 ...............415..*.........@......*...627*...................945*.............144/.506............................*......514...*...150...
 .........182..+.....873.756.......737........784..568....667..............258........./.........741...........707*....84........520.........
 "#;
-        let result = process_input(input.as_bytes());
+        let result = analyze(input);
         assert_eq!(
             (
                 31 + 339
@@ -389,7 +995,7 @@ mod tests {
                     + 520,
                 1995875
             ),
-            result
+            (result.sum, result.gear_ratios)
         );
     }
 
@@ -400,7 +1006,7 @@ mod tests {
     .............*........36..........743.=.../...............*......*..424.................580.#...897.448....*.......833...633.....*...*......
     .............963......................542........734.....901...914..........843.............523..........818..................691.....833...
         "#;
-        let result = process_input(input.as_bytes());
+        let result = analyze(input);
         assert_eq!(
             (
                 214 + 738
@@ -426,7 +1032,7 @@ mod tests {
                     + 833,
                 2640017
             ),
-            result
+            (result.sum, result.gear_ratios)
         );
     }
 
@@ -436,7 +1042,394 @@ mod tests {
 .*
 36
     "#;
-        let result = process_input(input.as_bytes());
-        assert_eq!((36, 0), result);
+        let result = analyze(input);
+        assert_eq!((36, 0), (result.sum, result.gear_ratios));
+    }
+
+    #[test]
+    fn test_process_input_gear_rules() {
+        let with_rule = |rule| {
+            let options = Options {
+                rule,
+                ..Options::default()
+            };
+            analyze_with(SAMPLE, &options)
+        };
+        let rule = GearRule {
+            aggregate: Aggregate::Sum,
+            ..GearRule::default()
+        };
+        let result = with_rule(rule);
+        assert_eq!(
+            (4361, 467 + 35 + 755 + 598),
+            (result.sum, result.gear_ratios)
+        );
+
+        let rule = GearRule {
+            neighbors: NeighborCount::Exactly(1),
+            ..GearRule::default()
+        };
+        let result = with_rule(rule);
+        assert_eq!(617, result.gear_ratios);
+        assert_eq!(2, result.overfull_gears.len());
+
+        let rule = GearRule {
+            neighbors: NeighborCount::AtLeast(1),
+            ..GearRule::default()
+        };
+        let result = with_rule(rule);
+        assert_eq!(467 * 35 + 617 + 755 * 598, result.gear_ratios);
+        assert!(result.overfull_gears.is_empty());
+
+        let rule = GearRule {
+            symbol: '#',
+            neighbors: NeighborCount::Exactly(1),
+            aggregate: Aggregate::Product,
+        };
+        let result = with_rule(rule);
+        assert_eq!(633, result.gear_ratios);
+    }
+
+    #[test]
+    fn test_process_input_rejects_overflowing_ratios() {
+        // Six 5-digit neighbours multiply to around 10^30.
+        let input = "\n99999.99999\n99999*99999\n99999.99999\n";
+        let rule = GearRule {
+            neighbors: NeighborCount::AtLeast(2),
+            ..GearRule::default()
+        };
+        let options = Options {
+            rule,
+            ..Options::default()
+        };
+        assert_eq!(
+            Err(SchematicError {
+                line: 3,
+                column: 6,
+                message: "gear at 5,1 takes the gear ratios past an i64".to_string(),
+            }),
+            process_input(input.as_bytes(), &options)
+        );
+        let options = Options {
+            rule: GearRule {
+                aggregate: Aggregate::Sum,
+                ..rule
+            },
+            ..Options::default()
+        };
+        assert_eq!(6 * 99999, analyze_with(input, &options).gear_ratios);
+
+        // Each ratio fits, but not their total.
+        let input = "3037000499*3037000499\n.\n3037000499*3037000499\n";
+        assert_eq!(
+            Err(SchematicError {
+                line: 3,
+                column: 11,
+                message: "gear at 10,2 takes the gear ratios past an i64".to_string(),
+            }),
+            process_input(input.as_bytes(), &Options::default())
+        );
+    }
+
+    #[test]
+    fn test_process_input_reports_overfull_gears() {
+        let input = r#"
+1.2
+.*.
+3..
+"#;
+        let result = analyze(input);
+        assert_eq!(0, result.gear_ratios);
+        assert!(result.gears.is_empty());
+        assert_eq!(
+            vec![Gear {
+                x: 1,
+                y: 1,
+                numbers: vec![1, 2, 3],
+            }],
+            result.overfull_gears
+        );
+    }
+
+    #[test]
+    fn test_process_input_ragged_rows() {
+        // The second row is longer than the first, and the number at its end
+        // touches the symbol below even though no earlier row reaches there.
+        let input = "1.\n.....12\n......*\n..3\n";
+        let result = analyze(input);
+        assert_eq!(12, result.sum);
+    }
+
+    #[test]
+    fn test_process_input_non_ascii() {
+        // '€' and 'é' are one column each, so 7 sits right of the gear.
+        let input = "€5..\n..é*7\n...2.\n";
+        let result = analyze(input);
+        assert_eq!(14, result.sum);
+        assert_eq!(14, result.gear_ratios);
+    }
+
+    #[test]
+    fn test_process_input_rejects_malformed() {
+        let input = "\n467..114..\n...*..\t...\n";
+        assert_eq!(
+            Err(SchematicError {
+                line: 3,
+                column: 7,
+                message: "unexpected control character '\\t'".to_string(),
+            }),
+            process_input(input.as_bytes(), &Options::default())
+        );
+        let input = "..*..\n.é9223372036854775808.\n";
+        let err = process_input(input.as_bytes(), &Options::default()).unwrap_err();
+        assert_eq!((2, 3), (err.line, err.column));
+        assert_eq!(
+            "line 2, column 3: part number does not fit in an i64",
+            err.to_string()
+        );
+        assert_eq!(9223372036854775807, analyze("9223372036854775807*\n").sum);
+        let input = "\n9223372036854775807*1\n";
+        assert_eq!(
+            Err(SchematicError {
+                line: 2,
+                column: 21,
+                message: "part number takes the sum past an i64".to_string(),
+            }),
+            process_input(input.as_bytes(), &Options::default())
+        );
+    }
+
+    #[test]
+    fn test_sparse_storage_agrees_with_dense() {
+        let mut rng = Rng::new(2023);
+        let mut any_rule = GearRule::default();
+        apply_gear_flag(&mut any_rule, "--gear-count", "1+").unwrap();
+        for round in 0..400 {
+            let input = random_schematic(&mut rng, [2, 10, 30, 60][round % 4]);
+            for rule in [GearRule::default(), any_rule] {
+                let dense = analyze_with(
+                    &input,
+                    &Options {
+                        rule,
+                        storage: Storage::Dense,
+                        ..Options::default()
+                    },
+                );
+                let sparse = analyze_with(
+                    &input,
+                    &Options {
+                        rule,
+                        storage: Storage::Sparse,
+                        ..Options::default()
+                    },
+                );
+                assert!(!dense.grid.is_sparse() && sparse.grid.is_sparse());
+                assert_eq!(dense.sum, sparse.sum, "{}", input);
+                assert_eq!(dense.gear_ratios, sparse.gear_ratios, "{}", input);
+                assert_eq!(dense.gears, sparse.gears, "{}", input);
+                assert_eq!(dense.overfull_gears, sparse.overfull_gears, "{}", input);
+                assert_eq!(dense.part_numbers, sparse.part_numbers, "{}", input);
+                assert_eq!(dense.parts, sparse.parts, "{}", input);
+                let (width, height) = (dense.grid.width(), dense.grid.height());
+                assert_eq!((width, height), (sparse.grid.width(), sparse.grid.height()));
+                for y in 0..height {
+                    for x in 0..width {
+                        assert_eq!(dense.grid.get(x, y), sparse.grid.get(x, y));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_auto_storage_follows_density() {
+        let sparse = format!("{}\n..7*..{}\n", ".".repeat(100), ".".repeat(100));
+        let analysis = analyze(&sparse);
+        assert!(analysis.grid.is_sparse());
+        assert_eq!(7, analysis.sum);
+        let analysis = analyze(SAMPLE);
+        assert!(!analysis.grid.is_sparse());
+        assert!(Storage::from_name("hash").is_err());
+    }
+
+    #[test]
+    fn test_process_input_adjacency_modes() {
+        let analyze = |input: &str, neighborhood, wrap| {
+            let options = Options {
+                adjacency: Adjacency { neighborhood, wrap },
+                ..Options::default()
+            };
+            let result = analyze_with(input, &options);
+            (result.sum, result.gear_ratios)
+        };
+        assert_eq!(
+            (4361, 467835),
+            analyze(SAMPLE, Neighborhood::Chebyshev(1), false)
+        );
+        // Only 35, 633, 617, 664 and 598 sit directly beside a symbol.
+        assert_eq!((2547, 0), analyze(SAMPLE, Neighborhood::Orthogonal, false));
+        // Every number is within two cells of a symbol, and every '*' now
+        // touches three or four numbers.
+        assert_eq!(
+            (4533, 0),
+            analyze(SAMPLE, Neighborhood::Chebyshev(2), false)
+        );
+        // Nothing in the sample reaches across an edge.
+        assert_eq!(
+            (4361, 467835),
+            analyze(SAMPLE, Neighborhood::Chebyshev(1), true)
+        );
+        assert_eq!(
+            (5, 0),
+            analyze("4..5\n...*\n", Neighborhood::Chebyshev(1), false)
+        );
+        assert_eq!(
+            (9, 20),
+            analyze("4..5\n...*\n", Neighborhood::Chebyshev(1), true)
+        );
+        assert_eq!((0, 0), analyze("3..*\n", Neighborhood::Orthogonal, false));
+        assert_eq!((3, 0), analyze("3..*\n", Neighborhood::Orthogonal, true));
+    }
+
+    #[test]
+    fn test_adjacency_from_name() {
+        assert_eq!(
+            Ok(Neighborhood::Orthogonal),
+            Adjacency::from_name("orthogonal")
+        );
+        assert_eq!(
+            Ok(Neighborhood::Chebyshev(1)),
+            Adjacency::from_name("surrounding")
+        );
+        assert_eq!(
+            Ok(Neighborhood::Chebyshev(3)),
+            Adjacency::from_name("radius:3")
+        );
+        assert!(Adjacency::from_name("radius:0").is_err());
+        assert!(Adjacency::from_name("radius:x").is_err());
+        assert!(Adjacency::from_name("hex").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_line_signed() {
+        let numbers = |signed| {
+            let tokenizer = Tokenizer {
+                signed,
+                tokens: Vec::new(),
+            };
+            let mut part_numbers = Vec::new();
+            let mut parts = Vec::new();
+            tokenize_line(&mut part_numbers, &mut parts, "..-42*-.5-3", 0, &tokenizer);
+            (
+                part_numbers
+                    .iter()
+                    .map(|n| (n.x, n.width, n.number))
+                    .collect::<Vec<_>>(),
+                parts.iter().map(|part| part.x).collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(
+            (vec![(2, 3, -42), (8, 1, 5), (9, 2, -3)], vec![5, 6]),
+            numbers(true)
+        );
+        assert_eq!(
+            (vec![(3, 2, 42), (8, 1, 5), (10, 1, 3)], vec![2, 5, 6, 9]),
+            numbers(false)
+        );
+    }
+
+    #[test]
+    fn test_tokenize_line_tokens() {
+        let mut tokenizer = Tokenizer::default();
+        tokenizer.add_tokens("->,**").unwrap();
+        tokenizer.add_tokens("<=>,->").unwrap();
+        let mut part_numbers = Vec::new();
+        let mut parts = Vec::new();
+        tokenize_line(&mut part_numbers, &mut parts, "1->2.<=>**3*", 0, &tokenizer);
+        assert_eq!(
+            vec![(1, 2, "->"), (5, 3, "<=>"), (8, 2, "**"), (11, 1, "*")],
+            parts
+                .iter()
+                .map(|part| (part.x, part.width, part.symbol.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![1, 2, 3],
+            part_numbers.iter().map(|n| n.number).collect::<Vec<_>>()
+        );
+        for invalid in ["", "-", "a1", "..", "< >"] {
+            assert!(
+                Tokenizer::default().add_tokens(invalid).is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_process_input_tokenizer() {
+        let analyze = |input: &str, tokenizer: &Tokenizer| {
+            let options = Options {
+                tokenizer: tokenizer.clone(),
+                ..Options::default()
+            };
+            analyze_with(input, &options)
+        };
+        let signed = Tokenizer {
+            signed: true,
+            tokens: Vec::new(),
+        };
+        let result = analyze("-5*3\n....\n", &signed);
+        assert_eq!((-2, -15), (result.sum, result.gear_ratios));
+        let result = analyze("-5*3\n....\n", &Tokenizer::default());
+        assert_eq!((8, 15), (result.sum, result.gear_ratios));
+        let result = analyze("4294967295#4294967295\n", &signed);
+        assert_eq!(8589934590, result.sum);
+        let result = analyze("-5000000000*3\n", &signed);
+        assert_eq!(
+            (-4999999997, -15000000000),
+            (result.sum, result.gear_ratios)
+        );
+
+        // The token's last cell is what reaches 9.
+        let mut tokens = Tokenizer::default();
+        tokens.add_tokens("<=>").unwrap();
+        let input = "7......\n.<=>...\n....9..\n";
+        let result = analyze(input, &tokens);
+        assert_eq!(
+            vec![Part {
+                x: 1,
+                y: 1,
+                width: 3,
+                symbol: "<=>".to_string(),
+            }],
+            result.parts
+        );
+        assert_eq!(vec![vec![0, 1]], result.adjacent_numbers);
+        assert_eq!(16, result.sum);
+        assert_eq!(3, analyze(input, &Tokenizer::default()).parts.len());
+    }
+
+    #[test]
+    fn test_apply_gear_flag() {
+        let mut rule = GearRule::default();
+        apply_gear_flag(&mut rule, "--gear-symbol", "#").unwrap();
+        apply_gear_flag(&mut rule, "--gear-count", "3+").unwrap();
+        apply_gear_flag(&mut rule, "--gear-aggregate", "sum").unwrap();
+        assert_eq!(
+            GearRule {
+                symbol: '#',
+                neighbors: NeighborCount::AtLeast(3),
+                aggregate: Aggregate::Sum,
+            },
+            rule
+        );
+        apply_gear_flag(&mut rule, "--gear-count", "4").unwrap();
+        assert_eq!(NeighborCount::Exactly(4), rule.neighbors);
+        assert!(apply_gear_flag(&mut rule, "--gear-symbol", "**").is_err());
+        assert!(apply_gear_flag(&mut rule, "--gear-symbol", "7").is_err());
+        assert!(apply_gear_flag(&mut rule, "--gear-count", "x").is_err());
+        assert!(apply_gear_flag(&mut rule, "--gear-aggregate", "max").is_err());
+        assert!(apply_gear_flag(&mut rule, "--gears", "1").is_err());
     }
 }
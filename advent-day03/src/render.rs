@@ -0,0 +1,325 @@
+use std::io::{self, Write};
+
+use crate::{Aggregate, Analysis, Cell, Gear, GearRule, Part};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Ansi,
+    Html,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Format, String> {
+        match name {
+            "ansi" => Ok(Format::Ansi),
+            "html" => Ok(Format::Html),
+            _ => Err(format!("unknown render format {:?}", name)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Style {
+    Plain,
+    Real,
+    NotPart,
+    Symbol,
+    Gear,
+}
+
+impl Style {
+    fn ansi(&self) -> &'static str {
+        match self {
+            Style::Plain => "\x1b[0m",
+            Style::Real => "\x1b[32m",
+            Style::NotPart => "\x1b[31m",
+            Style::Symbol => "\x1b[36m",
+            Style::Gear => "\x1b[1;33m",
+        }
+    }
+
+    fn class(&self) -> &'static str {
+        match self {
+            Style::Plain => "plain",
+            Style::Real => "real",
+            Style::NotPart => "not-part",
+            Style::Symbol => "symbol",
+            Style::Gear => "gear",
+        }
+    }
+}
+
+// The rows are the non-blank lines of `input`, which is how process_input
+// numbered them, so `analysis` must come from the same input.
+pub fn render<W: Write>(
+    input: &str,
+    analysis: &Analysis,
+    rule: &GearRule,
+    format: Format,
+    out: &mut W,
+) -> io::Result<()> {
+    let rows = input.lines().filter(|line| !line.trim().is_empty());
+    match format {
+        Format::Ansi => render_ansi(rows, analysis, rule, out),
+        Format::Html => render_html(rows, analysis, rule, out),
+    }
+}
+
+fn render_ansi<'a, W: Write>(
+    rows: impl Iterator<Item = &'a str>,
+    analysis: &Analysis,
+    rule: &GearRule,
+    out: &mut W,
+) -> io::Result<()> {
+    for (y, row) in rows.enumerate() {
+        let mut current = Style::Plain;
+        for (x, c) in row.chars().enumerate() {
+            let style = style_at(analysis, x, y);
+            if style != current {
+                write!(out, "{}", style.ansi())?;
+                current = style;
+            }
+            write!(out, "{}", c)?;
+        }
+        if current != Style::Plain {
+            write!(out, "{}", Style::Plain.ansi())?;
+        }
+        // Ratios go after the row so the schematic itself stays aligned.
+        let gears: Vec<String> = analysis
+            .gears
+            .iter()
+            .filter(|gear| gear.y as usize == y)
+            .map(|gear| format!("{},{} = {}", gear.x, gear.y, ratio_text(gear, rule)))
+            .collect();
+        if !gears.is_empty() {
+            write!(
+                out,
+                "  {}{}{}",
+                Style::Gear.ansi(),
+                gears.join("; "),
+                Style::Plain.ansi()
+            )?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn render_html<'a, W: Write>(
+    rows: impl Iterator<Item = &'a str>,
+    analysis: &Analysis,
+    rule: &GearRule,
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>Schematic</title>")?;
+    writeln!(out, "<style>")?;
+    writeln!(out, "body {{ background: #111; color: #888; }}")?;
+    writeln!(out, ".real {{ color: #4c4; }}")?;
+    writeln!(out, ".not-part {{ color: #e44; }}")?;
+    writeln!(out, ".symbol {{ color: #4cc; }}")?;
+    writeln!(out, ".gear {{ color: #ee4; font-weight: bold; }}")?;
+    writeln!(out, "span[title]:hover {{ background: #333; }}")?;
+    writeln!(out, "</style>")?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(
+        out,
+        "<p>sum: {}, gear ratios: {}</p>",
+        analysis.sum, analysis.gear_ratios
+    )?;
+    write!(out, "<pre>")?;
+    for (y, row) in rows.enumerate() {
+        let chars: Vec<char> = row.chars().collect();
+        let mut x = 0;
+        while x < chars.len() {
+            let style = style_at(analysis, x, y);
+            match analysis.grid.get(x, y) {
+                Some(&Cell::Number(index)) => {
+                    let part_number = &analysis.part_numbers[index];
+                    let end = (part_number.x + part_number.width) as usize;
+                    let text: String = chars[x..end].iter().collect();
+                    write!(
+                        out,
+                        "<span class=\"{}\" title=\"{}\">{}</span>",
+                        style.class(),
+                        escape(&number_tooltip(analysis, index)),
+                        escape(&text)
+                    )?;
+                    x = end;
+                    continue;
+                }
+                Some(&Cell::Symbol(index)) => {
+                    let part = &analysis.parts[index];
+                    let end = (part.x + part.width) as usize;
+                    let title = match gear_at(analysis, part.x as usize, y) {
+                        Some(gear) => format!(
+                            "gear '{}' at {},{}\n{}",
+                            part.symbol,
+                            part.x,
+                            part.y,
+                            ratio_text(gear, rule)
+                        ),
+                        None => format!("'{}' at {},{}", part.symbol, part.x, part.y),
+                    };
+                    write!(
+                        out,
+                        "<span class=\"{}\" title=\"{}\">{}</span>",
+                        style.class(),
+                        escape(&title),
+                        escape(&part.symbol)
+                    )?;
+                    x = end;
+                    continue;
+                }
+                _ => write!(out, "{}", escape(&chars[x].to_string()))?,
+            }
+            x += 1;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "</pre>")?;
+    if !analysis.gears.is_empty() {
+        writeln!(out, "<ul>")?;
+        for gear in &analysis.gears {
+            writeln!(
+                out,
+                "<li class=\"gear\">{},{}: {}</li>",
+                gear.x,
+                gear.y,
+                escape(&ratio_text(gear, rule))
+            )?;
+        }
+        writeln!(out, "</ul>")?;
+    }
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")?;
+    Ok(())
+}
+
+fn style_at(analysis: &Analysis, x: usize, y: usize) -> Style {
+    match analysis.grid.get(x, y) {
+        Some(&Cell::Number(index)) if analysis.part_numbers[index].real_part => Style::Real,
+        Some(&Cell::Number(_)) => Style::NotPart,
+        Some(&Cell::Symbol(index)) => {
+            let part = &analysis.parts[index];
+            match gear_at(analysis, part.x as usize, part.y as usize) {
+                Some(_) => Style::Gear,
+                None => Style::Symbol,
+            }
+        }
+        _ => Style::Plain,
+    }
+}
+
+fn gear_at(analysis: &Analysis, x: usize, y: usize) -> Option<&Gear> {
+    analysis
+        .gears
+        .iter()
+        .find(|gear| gear.x as usize == x && gear.y as usize == y)
+}
+
+// Read off the symbol-number graph, so the tooltip agrees with whatever
+// adjacency the analysis used.
+fn neighbors(analysis: &Analysis, index: usize) -> Vec<&Part> {
+    analysis
+        .parts
+        .iter()
+        .zip(&analysis.adjacent_numbers)
+        .filter(|(_, numbers)| numbers.contains(&index))
+        .map(|(part, _)| part)
+        .collect()
+}
+
+fn number_tooltip(analysis: &Analysis, index: usize) -> String {
+    let part_number = &analysis.part_numbers[index];
+    let neighbors = neighbors(analysis, index);
+    let neighbors = if neighbors.is_empty() {
+        "none".to_string()
+    } else {
+        neighbors
+            .iter()
+            .map(|part| format!("'{}' at {},{}", part.symbol, part.x, part.y))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    format!(
+        "{} at {},{} (width {})\nneighbors: {}",
+        part_number.number, part_number.x, part_number.y, part_number.width, neighbors
+    )
+}
+
+fn ratio_text(gear: &Gear, rule: &GearRule) -> String {
+    let operator = match rule.aggregate {
+        Aggregate::Product => " * ",
+        Aggregate::Sum => " + ",
+    };
+    let numbers: Vec<String> = gear.numbers.iter().map(|n| n.to_string()).collect();
+    // process_input has already checked that every gear's ratio fits.
+    let ratio = rule.ratio(&gear.numbers).unwrap();
+    format!("{} = {}", numbers.join(operator), ratio)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#10;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::analyze;
+
+    fn rendered(input: &str, format: Format) -> String {
+        let mut out = Vec::new();
+        render(
+            input,
+            &analyze(input),
+            &GearRule::default(),
+            format,
+            &mut out,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_render_ansi() {
+        let output = rendered("\n12..5\n..*.<\n.3...\n", Format::Ansi);
+        assert_eq!(
+            vec![
+                "\x1b[32m12\x1b[0m..\x1b[32m5\x1b[0m",
+                "..\x1b[1;33m*\x1b[0m.\x1b[36m<\x1b[0m  \x1b[1;33m2,1 = 12 * 3 = 36\x1b[0m",
+                ".\x1b[32m3\x1b[0m...",
+            ],
+            output.lines().collect::<Vec<&str>>()
+        );
+        assert!(rendered("7....\n", Format::Ansi).starts_with("\x1b[31m7\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_html() {
+        let output = rendered("12..5\n..*.<\n.3...\n", Format::Html);
+        assert!(output.starts_with("<!DOCTYPE html>"));
+        assert!(output.contains(
+            "<span class=\"real\" title=\"12 at 0,0 (width 2)&#10;neighbors: '*' at 2,1\">12</span>"
+        ));
+        assert!(output
+            .contains("<span class=\"gear\" title=\"gear '*' at 2,1&#10;12 * 3 = 36\">*</span>"));
+        assert!(output.contains("<span class=\"symbol\" title=\"'&lt;' at 4,1\">&lt;</span>"));
+        assert!(output.contains("<li class=\"gear\">2,1: 12 * 3 = 36</li>"));
+    }
+}
@@ -0,0 +1,318 @@
+use std::io::{self, BufRead, Read};
+
+use crate::{
+    process_line, ratio_overflow, sum_overflow, validate_line, Gear, GearRule, Part, PartNumber,
+    SchematicError,
+};
+
+// Solves a schematic one row at a time. A row can only be settled once the
+// row below it is known, so the window holds the previous, current and next
+// rows and everything older is dropped: memory depends on the row width, not
+// on how many rows there are.
+
+#[derive(Debug, PartialEq)]
+pub struct RowSummary {
+    pub y: u32,
+    pub sum: i64,
+    pub gear_ratios: i64,
+    pub gears: Vec<Gear>,
+    pub overfull_gears: Vec<Gear>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Totals {
+    pub sum: i64,
+    pub gear_ratios: i64,
+}
+
+struct Row {
+    y: u32,
+    part_numbers: Vec<PartNumber>,
+    parts: Vec<Part>,
+}
+
+pub struct Window<'a> {
+    rule: &'a GearRule,
+    previous: Option<Row>,
+    current: Option<Row>,
+    rows: u32,
+    totals: Totals,
+}
+
+impl<'a> Window<'a> {
+    pub fn new(rule: &'a GearRule) -> Window<'a> {
+        Window {
+            rule,
+            previous: None,
+            current: None,
+            rows: 0,
+            totals: Totals {
+                sum: 0,
+                gear_ratios: 0,
+            },
+        }
+    }
+
+    // Adds the next row and returns the summary of the row above it, which
+    // is now complete. Fails like validate_line, with the column and message
+    // for the number or gear in that row that takes a total past an i64.
+    pub fn push(&mut self, line: &str) -> Result<Option<RowSummary>, (usize, String)> {
+        let mut next = Row {
+            y: self.rows,
+            part_numbers: Vec::new(),
+            parts: Vec::new(),
+        };
+        process_line(&mut next.part_numbers, &mut next.parts, line, self.rows);
+        self.rows += 1;
+        self.advance(Some(next))
+    }
+
+    // Settles the last row, if there was one.
+    pub fn finish(&mut self) -> Result<Option<RowSummary>, (usize, String)> {
+        self.advance(None)
+    }
+
+    fn advance(&mut self, next: Option<Row>) -> Result<Option<RowSummary>, (usize, String)> {
+        let summary = self
+            .current
+            .as_ref()
+            .map(|current| {
+                let window = [self.previous.as_ref(), Some(current), next.as_ref()];
+                summarize(current, window, self.rule, &mut self.totals)
+            })
+            .transpose()?;
+        self.previous = self.current.take();
+        self.current = next;
+        Ok(summary)
+    }
+}
+
+fn summarize(
+    current: &Row,
+    window: [Option<&Row>; 3],
+    rule: &GearRule,
+    totals: &mut Totals,
+) -> Result<RowSummary, (usize, String)> {
+    let rows: Vec<&Row> = window.into_iter().flatten().collect();
+    let mut sum: i64 = 0;
+    for part_number in current.part_numbers.iter().filter(|part_number| {
+        rows.iter()
+            .flat_map(|row| &row.parts)
+            .any(|part| touches(part_number, part))
+    }) {
+        let number = part_number.number;
+        sum = sum
+            .checked_add(number)
+            .ok_or_else(|| sum_overflow(part_number))?;
+        totals.sum = totals
+            .sum
+            .checked_add(number)
+            .ok_or_else(|| sum_overflow(part_number))?;
+    }
+    let mut gears = Vec::new();
+    let mut overfull_gears = Vec::new();
+    for part in current.parts.iter().filter(|part| part.is(rule.symbol)) {
+        let gear = Gear {
+            x: part.x,
+            y: part.y,
+            numbers: rows
+                .iter()
+                .flat_map(|row| &row.part_numbers)
+                .filter(|part_number| touches(part_number, part))
+                .map(|part_number| part_number.number)
+                .collect(),
+        };
+        if rule.accepts(gear.numbers.len()) {
+            gears.push(gear);
+        } else if rule.overfull(gear.numbers.len()) {
+            overfull_gears.push(gear);
+        }
+    }
+    let gear_ratios = rule.add_ratios(0, &gears).map_err(ratio_overflow)?;
+    totals.gear_ratios = rule
+        .add_ratios(totals.gear_ratios, &gears)
+        .map_err(ratio_overflow)?;
+    Ok(RowSummary {
+        y: current.y,
+        sum,
+        gear_ratios,
+        gears,
+        overfull_gears,
+    })
+}
+
+fn touches(part_number: &PartNumber, part: &Part) -> bool {
+    part.y + 1 >= part_number.y
+        && part.y <= part_number.y + 1
+        && part.x + part.width >= part_number.x
+        && part.x <= part_number.x + part_number.width
+}
+
+// Feeds `reader` through a Window, handing every settled row to `emit`.
+pub fn stream_input<R: Read, F: FnMut(&RowSummary)>(
+    reader: R,
+    rule: &GearRule,
+    mut emit: F,
+) -> Result<Totals, SchematicError> {
+    let mut window = Window::new(rule);
+    // The input lines of the last two rows pushed: pushing a row settles
+    // the one before it.
+    let mut lines = (0, 0);
+    let buffered = io::BufReader::new(reader);
+    for (index, line_result) in buffered.lines().enumerate() {
+        let line = line_result.map_err(|err| SchematicError {
+            line: index + 1,
+            column: 1,
+            message: err.to_string(),
+        })?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        validate_line(&line).map_err(|(column, message)| SchematicError {
+            line: index + 1,
+            column,
+            message,
+        })?;
+        lines = (lines.1, index + 1);
+        let settled = window
+            .push(&line)
+            .map_err(|overflow| SchematicError::at(lines.0, overflow))?;
+        if let Some(summary) = settled {
+            emit(&summary);
+        }
+    }
+    let settled = window
+        .finish()
+        .map_err(|overflow| SchematicError::at(lines.1, overflow))?;
+    if let Some(summary) = settled {
+        emit(&summary);
+    }
+    Ok(window.totals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::analyze_with;
+    use crate::Options;
+
+    fn assert_matches_process_input(input: &str, rule: &GearRule) {
+        let analysis = analyze_with(
+            input,
+            &Options {
+                rule: *rule,
+                ..Options::default()
+            },
+        );
+        let mut gears = Vec::new();
+        let mut overfull_gears = Vec::new();
+        let mut rows = Vec::new();
+        let totals = stream_input(input.as_bytes(), rule, |summary| {
+            rows.push(summary.y);
+            gears.extend(
+                summary
+                    .gears
+                    .iter()
+                    .map(|gear| (gear.x, gear.y, gear.numbers.clone())),
+            );
+            overfull_gears.extend(summary.overfull_gears.iter().map(|gear| (gear.x, gear.y)));
+        })
+        .unwrap();
+        assert_eq!(
+            Totals {
+                sum: analysis.sum,
+                gear_ratios: analysis.gear_ratios,
+            },
+            totals
+        );
+        assert_eq!(
+            analysis
+                .gears
+                .iter()
+                .map(|gear| (gear.x, gear.y, gear.numbers.clone()))
+                .collect::<Vec<_>>(),
+            gears
+        );
+        assert_eq!(
+            analysis
+                .overfull_gears
+                .iter()
+                .map(|gear| (gear.x, gear.y))
+                .collect::<Vec<_>>(),
+            overfull_gears
+        );
+        assert_eq!(
+            (0..analysis.grid.height() as u32).collect::<Vec<u32>>(),
+            rows
+        );
+    }
+
+    #[test]
+    fn test_stream_matches_process_input() {
+        let inputs = [
+            include_str!("../puzzle.input"),
+            crate::tests::SAMPLE,
+            "1.2\n.*.\n3..\n",
+            "5*5",
+            "\n\n..\n\n7\n#\n",
+            "1.\n.....12\n......*\n..3\n",
+            "€5..\n..é*7\n...2.\n",
+        ];
+        for input in inputs {
+            assert_matches_process_input(input, &GearRule::default());
+            let mut rule = GearRule::default();
+            crate::apply_gear_flag(&mut rule, "--gear-count", "1+").unwrap();
+            crate::apply_gear_flag(&mut rule, "--gear-aggregate", "sum").unwrap();
+            assert_matches_process_input(input, &rule);
+        }
+    }
+
+    #[test]
+    fn test_window_emits_rows_as_they_settle() {
+        let rule = GearRule::default();
+        let mut window = Window::new(&rule);
+        assert_eq!(Ok(None), window.push("12.."));
+        let first = window.push("..*3").unwrap().unwrap();
+        assert_eq!((0, 12, 0), (first.y, first.sum, first.gear_ratios));
+        let second = window.push("....").unwrap().unwrap();
+        assert_eq!((1, 3, 36), (second.y, second.sum, second.gear_ratios));
+        assert_eq!(0, window.finish().unwrap().unwrap().sum);
+        assert_eq!(Ok(None), window.finish());
+    }
+
+    #[test]
+    fn test_stream_reports_malformed_lines() {
+        let err = stream_input("..\n.\t.\n".as_bytes(), &GearRule::default(), |_| {}).unwrap_err();
+        assert_eq!((2, 2), (err.line, err.column));
+    }
+
+    #[test]
+    fn test_stream_rejects_overflowing_totals() {
+        let rule = GearRule {
+            neighbors: crate::NeighborCount::AtLeast(2),
+            ..GearRule::default()
+        };
+        let input = "\n99999.99999\n99999*99999\n\n99999.99999\n";
+        let err = stream_input(input.as_bytes(), &rule, |_| {}).unwrap_err();
+        assert_eq!(
+            (3, 6, "gear at 5,1 takes the gear ratios past an i64"),
+            (err.line, err.column, err.message.as_str())
+        );
+
+        // The last row is only settled by finish().
+        let input = "3037000499*3037000499\n.\n\n3037000499*3037000499\n";
+        let err = stream_input(input.as_bytes(), &GearRule::default(), |_| {}).unwrap_err();
+        assert_eq!(
+            (4, 11, "gear at 10,2 takes the gear ratios past an i64"),
+            (err.line, err.column, err.message.as_str())
+        );
+
+        // Each row's sum fits, but not the total.
+        let input = "9223372036854775807*\n.\n\n..*1\n";
+        let err = stream_input(input.as_bytes(), &GearRule::default(), |_| {}).unwrap_err();
+        assert_eq!(
+            (4, 4, "part number takes the sum past an i64"),
+            (err.line, err.column, err.message.as_str())
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::graph::{comma, escape};
+use crate::{Analysis, SchematicError};
+
+// A number touching two symbols of the same type is only counted once for
+// that type, so `sum` is the sum of distinct numbers.
+#[derive(Debug, PartialEq)]
+pub struct SymbolStats {
+    pub symbol: String,
+    pub occurrences: usize,
+    pub numbers: Vec<usize>,
+    pub sum: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Breakdown {
+    pub symbols: Vec<SymbolStats>,
+    // Numbers touching more than one type of symbol, with those types.
+    pub shared: Vec<(usize, Vec<String>)>,
+}
+
+pub fn breakdown(analysis: &Analysis) -> Result<Breakdown, SchematicError> {
+    let mut types_of: Vec<Vec<String>> = vec![Vec::new(); analysis.part_numbers.len()];
+    let mut stats: BTreeMap<&str, SymbolStats> = BTreeMap::new();
+    for (part, numbers) in analysis.parts.iter().zip(&analysis.adjacent_numbers) {
+        let entry = stats.entry(&part.symbol).or_insert_with(|| SymbolStats {
+            symbol: part.symbol.clone(),
+            occurrences: 0,
+            numbers: Vec::new(),
+            sum: 0,
+        });
+        entry.occurrences += 1;
+        for &number in numbers {
+            if !types_of[number].contains(&part.symbol) {
+                types_of[number].push(part.symbol.clone());
+                entry.numbers.push(number);
+                let part_number = &analysis.part_numbers[number];
+                entry.sum = entry
+                    .sum
+                    .checked_add(part_number.number)
+                    .ok_or_else(|| analysis.sum_overflow(part_number))?;
+            }
+        }
+    }
+    let mut symbols: Vec<SymbolStats> = stats.into_values().collect();
+    for stat in &mut symbols {
+        stat.numbers.sort_unstable();
+    }
+    let shared = types_of
+        .into_iter()
+        .enumerate()
+        .filter(|(_, types)| types.len() > 1)
+        .map(|(number, mut types)| {
+            types.sort_unstable();
+            (number, types)
+        })
+        .collect();
+    Ok(Breakdown { symbols, shared })
+}
+
+pub fn write_text<W: Write>(analysis: &Analysis, out: &mut W) -> io::Result<()> {
+    let breakdown = breakdown(analysis)?;
+    for stat in &breakdown.symbols {
+        writeln!(
+            out,
+            "'{}': {} symbols, {} numbers, sum {}: {}",
+            stat.symbol,
+            stat.occurrences,
+            stat.numbers.len(),
+            stat.sum,
+            number_list(analysis, &stat.numbers)
+        )?;
+    }
+    if !breakdown.shared.is_empty() {
+        writeln!(out, "numbers touching several symbol types:")?;
+        for (number, types) in &breakdown.shared {
+            let part_number = &analysis.part_numbers[*number];
+            let types: Vec<String> = types.iter().map(|symbol| format!("'{}'", symbol)).collect();
+            writeln!(
+                out,
+                "{} at {},{}: {}",
+                part_number.number,
+                part_number.x,
+                part_number.y,
+                types.join(", ")
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_json<W: Write>(analysis: &Analysis, out: &mut W) -> io::Result<()> {
+    let breakdown = breakdown(analysis)?;
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"symbols\": [")?;
+    for (index, stat) in breakdown.symbols.iter().enumerate() {
+        writeln!(
+            out,
+            "    {{\"symbol\": \"{}\", \"occurrences\": {}, \"count\": {}, \"sum\": {}, \"numbers\": [{}]}}{}",
+            escape(&stat.symbol),
+            stat.occurrences,
+            stat.numbers.len(),
+            stat.sum,
+            number_list(analysis, &stat.numbers),
+            comma(index, breakdown.symbols.len())
+        )?;
+    }
+    writeln!(out, "  ],")?;
+    writeln!(out, "  \"shared\": [")?;
+    for (index, (number, types)) in breakdown.shared.iter().enumerate() {
+        let part_number = &analysis.part_numbers[*number];
+        let types: Vec<String> = types
+            .iter()
+            .map(|symbol| format!("\"{}\"", escape(symbol)))
+            .collect();
+        writeln!(
+            out,
+            "    {{\"number\": {}, \"x\": {}, \"y\": {}, \"symbols\": [{}]}}{}",
+            part_number.number,
+            part_number.x,
+            part_number.y,
+            types.join(", "),
+            comma(index, breakdown.shared.len())
+        )?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn number_list(analysis: &Analysis, numbers: &[usize]) -> String {
+    numbers
+        .iter()
+        .map(|&number| analysis.part_numbers[number].number.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{analyze, analyze_with, SAMPLE};
+    use crate::{Options, Tokenizer};
+
+    #[test]
+    fn test_breakdown_sample() {
+        let analysis = analyze(SAMPLE);
+        let breakdown = breakdown(&analysis).unwrap();
+        assert_eq!(
+            vec![
+                ("#", 1, 1, 633),
+                ("$", 1, 1, 664),
+                ("*", 3, 5, 467 + 35 + 617 + 755 + 598),
+                ("+", 1, 1, 592),
+            ],
+            breakdown
+                .symbols
+                .iter()
+                .map(|stat| (
+                    stat.symbol.as_str(),
+                    stat.occurrences,
+                    stat.numbers.len(),
+                    stat.sum
+                ))
+                .collect::<Vec<_>>()
+        );
+        assert!(breakdown.shared.is_empty());
+        assert_eq!(
+            analysis.sum,
+            breakdown.symbols.iter().map(|stat| stat.sum).sum::<i64>()
+        );
+    }
+
+    #[test]
+    fn test_breakdown_shared_numbers() {
+        // 12 touches '#' and both '*'s, but only counts once for '*'.
+        let analysis = analyze("*....\n.12#.\n*3...\n");
+        let mut out = Vec::new();
+        write_text(&analysis, &mut out).unwrap();
+        assert_eq!(
+            "'#': 1 symbols, 1 numbers, sum 12: 12\n\
+             '*': 2 symbols, 2 numbers, sum 15: 12, 3\n\
+             numbers touching several symbol types:\n\
+             12 at 1,1: '#', '*'\n",
+            String::from_utf8(out).unwrap()
+        );
+        let mut out = Vec::new();
+        write_json(&analysis, &mut out).unwrap();
+        assert_eq!(
+            "{\n  \"symbols\": [\n\
+             \x20   {\"symbol\": \"#\", \"occurrences\": 1, \"count\": 1, \"sum\": 12, \"numbers\": [12]},\n\
+             \x20   {\"symbol\": \"*\", \"occurrences\": 2, \"count\": 2, \"sum\": 15, \"numbers\": [12, 3]}\n\
+             \x20 ],\n  \"shared\": [\n\
+             \x20   {\"number\": 12, \"x\": 1, \"y\": 1, \"symbols\": [\"#\", \"*\"]}\n\
+             \x20 ]\n}\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_breakdown_rejects_overflowing_sums() {
+        // The overall sum fits, but not the sum of the two numbers at '#'.
+        let input = "-9000000000000000000*\n.\n9000000000000000000#9000000000000000000\n";
+        let options = Options {
+            tokenizer: Tokenizer {
+                signed: true,
+                ..Tokenizer::default()
+            },
+            ..Options::default()
+        };
+        let analysis = analyze_with(input, &options);
+        assert_eq!(9000000000000000000, analysis.sum);
+        let err = breakdown(&analysis).unwrap_err();
+        assert_eq!(
+            "line 3, column 21: part number takes the sum past an i64",
+            err.to_string()
+        );
+        assert!(write_text(&analysis, &mut Vec::new()).is_err());
+    }
+}
@@ -1,116 +1,277 @@
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::collections::{HashSet, VecDeque};
 use std::io::{self, BufRead, Read};
+use std::process;
+
+#[derive(Debug, PartialEq)]
+struct Card {
+    id: u32,
+    value: u64,
+    matches: u32,
+}
+
+// Cards are played in id order whatever order the file lists them in. Only
+// the first card with a given id counts; later ones are listed in
+// `duplicates`. Copies won of an id in `missing` are lost. The gaps are
+// inclusive ranges, as a sparse file can skip millions of ids at once.
+#[derive(Debug, PartialEq)]
+struct Scratchcards {
+    sum: u64,
+    scratchcard_count: u64,
+    duplicates: Vec<u32>,
+    missing: Vec<(u32, u32)>,
+}
 
 fn main() {
-    let (sum, scratchcard_count) = process_input(io::stdin().lock());
-    println!("sum: {}, scratchcard_count: {}", sum, scratchcard_count);
+    let result = process_input(io::stdin().lock()).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    });
+    for id in &result.duplicates {
+        eprintln!("warning: duplicate card {} ignored", id);
+    }
+    for &(first, last) in &result.missing {
+        if first == last {
+            eprintln!("warning: missing card {}", first);
+        } else {
+            eprintln!("warning: missing cards {} to {}", first, last);
+        }
+    }
+    println!(
+        "sum: {}, scratchcard_count: {}",
+        result.sum, result.scratchcard_count
+    );
 }
 
-fn process_input<R: Read>(reader: R) -> (u32, u32) {
+fn process_input<R: Read>(reader: R) -> Result<Scratchcards, String> {
     let buffered = io::BufReader::new(reader);
-    let mut sum = 0;
-    let mut scratchcard_count = 0;
-    let mut multipliers: Vec<u32> = Vec::new();
-    for line_result in buffered.lines() {
-        let line = line_result.unwrap();
+    let mut cards: BTreeMap<u32, Card> = BTreeMap::new();
+    let mut duplicates = Vec::new();
+    for (index, line_result) in buffered.lines().enumerate() {
+        let line = line_result.map_err(|err| format!("line {}: {}", index + 1, err))?;
         if line.trim().is_empty() {
             continue;
         }
-        let mut current_multiplier = 1;
-
-        if let Some(_first_element) = multipliers.get(0) {
-            let first_element = multipliers.remove(0);
-            current_multiplier += first_element;
+        let card = process_line(&line).map_err(|err| format!("line {}: {}", index + 1, err))?;
+        match cards.entry(card.id) {
+            Entry::Occupied(_) => duplicates.push(card.id),
+            Entry::Vacant(entry) => {
+                entry.insert(card);
+            }
+        }
+    }
+    let mut missing = Vec::new();
+    let mut previous = 0;
+    for &id in cards.keys() {
+        if id - previous > 1 {
+            missing.push((previous + 1, id - 1));
         }
-        scratchcard_count += current_multiplier;
-        let (card_value, counts) = process_line(&line);
-        for i in 1..=(counts as usize) {
-            if i > multipliers.len() {
-                multipliers.push(0);
+        previous = id;
+    }
+
+    let (sum, scratchcard_count) = play(cards.values())?;
+    Ok(Scratchcards {
+        sum,
+        scratchcard_count,
+        duplicates,
+        missing,
+    })
+}
+
+// Returns the sum of the card values and how many cards there are once all
+// the copies are won, or an error naming the card that takes either past a
+// u64. `cards` must be in id order.
+fn play<'a>(cards: impl Iterator<Item = &'a Card>) -> Result<(u64, u64), String> {
+    let mut sum: u64 = 0;
+    let mut scratchcard_count: u64 = 0;
+    // copies[k] is the number of extra copies won so far of card next_id + k.
+    let mut copies: VecDeque<u64> = VecDeque::new();
+    // A u64 so that it can go past the last possible id.
+    let mut next_id: u64 = 1;
+    for card in cards {
+        let skipped = ((u64::from(card.id) - next_id) as usize).min(copies.len());
+        copies.drain(..skipped);
+        let overflow = || format!("card {} takes the scratchcard count past a u64", card.id);
+        let current_multiplier = copies
+            .pop_front()
+            .unwrap_or(0)
+            .checked_add(1)
+            .ok_or_else(overflow)?;
+        next_id = u64::from(card.id) + 1;
+        scratchcard_count = scratchcard_count
+            .checked_add(current_multiplier)
+            .ok_or_else(overflow)?;
+        for offset in 0..card.matches as usize {
+            if offset == copies.len() {
+                copies.push_back(0);
             }
-            multipliers[i - 1] += current_multiplier;
+            copies[offset] = copies[offset]
+                .checked_add(current_multiplier)
+                .ok_or_else(overflow)?;
         }
-        sum += card_value;
+        sum = sum
+            .checked_add(card.value)
+            .ok_or_else(|| format!("card {} takes the sum past a u64", card.id))?;
     }
-    (sum, scratchcard_count)
+    Ok((sum, scratchcard_count))
+}
+
+// Card numbers are nearly always below 128, so those live in a bitmask and
+// only the rest need hashing.
+#[derive(Default)]
+struct NumberSet {
+    small: u128,
+    large: HashSet<u32>,
 }
 
-fn process_line(line: &str) -> (u32, u32) {
-    let mut parts = line.split(":");
-    if parts.clone().count() != 2 {
-        panic!("invalid line: {}", line);
+impl NumberSet {
+    fn insert(&mut self, number: u32) {
+        if number < 128 {
+            self.small |= 1 << number;
+        } else {
+            self.large.insert(number);
+        }
     }
-    let _card = parts.next().expect("No card part found");
-    let numbers_part = parts.next().expect("No numbers part found");
-    let mut number_part_segments = numbers_part.split("|");
-    if number_part_segments.clone().count() != 2 {
-        panic!("invalid line: {}", line);
+
+    fn contains(&self, number: u32) -> bool {
+        if number < 128 {
+            self.small & (1 << number) != 0
+        } else {
+            self.large.contains(&number)
+        }
     }
-    let winners = number_part_segments
-        .next()
-        .expect("No winners found")
-        .split_whitespace()
-        .map(|s| s.parse::<u32>().expect("Invalid number"))
-        .collect::<Vec<u32>>();
-    let mine = number_part_segments
-        .next()
-        .expect("No 'my numbers' found")
-        .split_whitespace()
-        .map(|s| s.parse::<u32>().expect("Invalid number"))
-        .collect::<Vec<u32>>();
-    let winner_count = mine.iter().filter(|&m| winners.contains(m)).count();
-    if winner_count == 0 {
-        (0, 0)
+}
+
+fn process_line(line: &str) -> Result<Card, String> {
+    let (card_part, numbers_part) = line
+        .split_once(':')
+        .ok_or_else(|| format!("missing ':' in {:?}", line))?;
+    let id = match card_part.split_whitespace().collect::<Vec<&str>>()[..] {
+        ["Card", id] => id
+            .parse::<u32>()
+            .map_err(|_| format!("invalid card id {:?}", id))?,
+        _ => return Err(format!("expected \"Card <id>\" but got {:?}", card_part)),
+    };
+    if id == 0 {
+        return Err("card ids start at 1".to_string());
+    }
+    let (winners_part, mine_part) = numbers_part
+        .split_once('|')
+        .ok_or_else(|| format!("missing '|' in card {}", id))?;
+    let winner_count = count_matches(&parse_numbers(winners_part)?, &parse_numbers(mine_part)?);
+    let value = if winner_count == 0 {
+        0
     } else {
-        (1 << winner_count - 1, winner_count as u32)
+        1u64.checked_shl(winner_count - 1).ok_or_else(|| {
+            format!(
+                "card {} has {} matches, too many for its value to fit in a u64",
+                id, winner_count
+            )
+        })?
+    };
+    Ok(Card {
+        id,
+        value,
+        matches: winner_count,
+    })
+}
+
+fn count_matches(winners: &[u32], mine: &[u32]) -> u32 {
+    let mut set = NumberSet::default();
+    for &winner in winners {
+        set.insert(winner);
     }
+    mine.iter().filter(|&&m| set.contains(m)).count() as u32
+}
+
+fn parse_numbers(segment: &str) -> Result<Vec<u32>, String> {
+    segment
+        .split_whitespace()
+        .map(|s| {
+            s.parse::<u32>()
+                .map_err(|_| format!("invalid number {:?}", s))
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn score(line: &str) -> (u64, u32) {
+        let card = process_line(line).unwrap();
+        (card.value, card.matches)
+    }
+
     #[test]
     fn test_parse_line_1() {
         let line = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53";
-        let score = process_line(line);
+        let score = score(line);
         assert_eq!((8, 4), score);
     }
 
     #[test]
     fn test_parse_line_2() {
         let line = "Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19";
-        let score = process_line(line);
+        let score = score(line);
         assert_eq!((2, 2), score);
     }
 
     #[test]
     fn test_parse_line_3() {
         let line = "Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1";
-        let score = process_line(line);
+        let score = score(line);
         assert_eq!((2, 2), score);
     }
 
     #[test]
     fn test_parse_line_4() {
         let line = "Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83";
-        let score = process_line(line);
+        let score = score(line);
         assert_eq!((1, 1), score);
     }
 
     #[test]
     fn test_parse_line_5() {
         let line = "Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36";
-        let score = process_line(line);
+        let score = score(line);
         assert_eq!((0, 0), score);
     }
 
     #[test]
     fn test_parse_line_6() {
         let line = "Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
-        let score = process_line(line);
+        let score = score(line);
         assert_eq!((0, 0), score);
     }
 
+    #[test]
+    fn test_parse_line_many_matches() {
+        let card = |matches: u32| {
+            let numbers: Vec<String> = (1..=matches).map(|n| n.to_string()).collect();
+            let numbers = numbers.join(" ");
+            format!("Card 1: {} | {}", numbers, numbers)
+        };
+        assert_eq!((1 << 39, 40), score(&card(40)));
+        assert_eq!((1 << 63, 64), score(&card(64)));
+        assert_eq!(
+            Err("card 1 has 65 matches, too many for its value to fit in a u64".to_string()),
+            process_line(&card(65))
+        );
+    }
+
+    #[test]
+    fn test_parse_line_card_id() {
+        assert_eq!(117, process_line("Card 117: 1 | 2").unwrap().id);
+        assert!(process_line("Card: 1 | 2").is_err());
+        assert!(process_line("Card x: 1 | 2").is_err());
+        assert!(process_line("Card 0: 1 | 2").is_err());
+        assert!(process_line("Crad 1: 1 | 2").is_err());
+        assert!(process_line("Card 1 1 | 2").is_err());
+        assert!(process_line("Card 1: 1 2").is_err());
+        assert!(process_line("Card 1: 1 | x").is_err());
+    }
+
     #[test]
     fn test_process_input_sample() {
         let input = r#"
@@ -121,7 +282,213 @@ Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
 Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
 Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11
     "#;
-        let result = process_input(input.as_bytes());
-        assert_eq!((13, 30), result);
+        let result = process_input(input.as_bytes()).unwrap();
+        assert_eq!((13, 30), (result.sum, result.scratchcard_count));
+        assert!(result.duplicates.is_empty());
+        assert!(result.missing.is_empty());
+    }
+
+    #[test]
+    fn test_process_input_shuffled() {
+        let input = r#"
+Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
+Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
+Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11
+Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
+Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
+"#;
+        let result = process_input(input.as_bytes()).unwrap();
+        assert_eq!((13, 30), (result.sum, result.scratchcard_count));
+    }
+
+    #[test]
+    fn test_process_input_gaps_and_duplicates() {
+        // Card 1 wins copies of 2 and 3, but only 3 exists; the second card 3
+        // is ignored.
+        let input = "Card 1: 1 2 | 1 2\nCard 3: 5 | 5\nCard 3: 6 | 7\nCard 4: 8 | 9\n";
+        let result = process_input(input.as_bytes()).unwrap();
+        assert_eq!(
+            Scratchcards {
+                sum: 2 + 1,
+                scratchcard_count: 1 + 2 + 3,
+                duplicates: vec![3],
+                missing: vec![(2, 2)],
+            },
+            result
+        );
+    }
+
+    #[test]
+    fn test_process_input_reports_line() {
+        let err = process_input("Card 1: 1 | 2\n\nCard 2: 1 | 2 x\n".as_bytes()).unwrap_err();
+        assert_eq!("line 3: invalid number \"x\"", err);
+    }
+
+    #[test]
+    fn test_number_set() {
+        let mut set = NumberSet::default();
+        for number in [0, 5, 127, 128, 4_000_000_000] {
+            set.insert(number);
+        }
+        for number in [0, 5, 127, 128, 4_000_000_000] {
+            assert!(set.contains(number));
+        }
+        for number in [1, 126, 129, 4_000_000_001] {
+            assert!(!set.contains(number));
+        }
+        let line = "Card 1: 500 3 200 | 200 4 500 3";
+        assert_eq!((4, 3), score(line));
+    }
+
+    #[test]
+    fn test_process_input_copies_past_gaps() {
+        // Card 2 wins copies of 3 to 6; 4 and 5 are missing, so only 3 and
+        // 6 get them.
+        let input = "Card 2: 1 2 3 4 | 1 2 3 4\nCard 3: 9 | 8\nCard 6: 9 | 8\nCard 7: 9 | 8\n";
+        let result = process_input(input.as_bytes()).unwrap();
+        assert_eq!((8, 1 + 2 + 2 + 1), (result.sum, result.scratchcard_count));
+        assert_eq!(vec![(1, 1), (4, 5)], result.missing);
+    }
+
+    #[test]
+    fn test_process_input_rejects_overflowing_sum() {
+        let numbers: Vec<String> = (1..=64).map(|n| n.to_string()).collect();
+        let numbers = numbers.join(" ");
+        let card = |id| format!("Card {}: {} | {}\n", id, numbers, numbers);
+        let input = card(1) + &card(2);
+        assert_eq!(
+            Err("card 2 takes the sum past a u64".to_string()),
+            process_input(input.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_process_input_rejects_overflowing_counts() {
+        // Every card doubles the copies of the next 40, which overflows a
+        // u64 long before the sum does.
+        let numbers: Vec<String> = (1..=40).map(|n| n.to_string()).collect();
+        let numbers = numbers.join(" ");
+        let input: String = (1..=79)
+            .map(|id| format!("Card {}: {} | {}\n", id, numbers, numbers))
+            .collect();
+        assert_eq!(
+            Err("card 65 takes the scratchcard count past a u64".to_string()),
+            process_input(input.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_process_input_sparse_ids() {
+        let input = "Card 1: 1 | 1\nCard 30000000: 1 | 2\nCard 4294967295: 3 | 3\n";
+        let result = process_input(input.as_bytes()).unwrap();
+        assert_eq!((2, 3), (result.sum, result.scratchcard_count));
+        assert_eq!(vec![(2, 29999999), (30000001, 4294967294)], result.missing);
+    }
+
+    // The versions these replaced: winners in a Vec, and the copies in a Vec
+    // shifted down with remove(0) for every card.
+    fn naive_count_matches(winners: &[u32], mine: &[u32]) -> u32 {
+        mine.iter().filter(|&m| winners.contains(m)).count() as u32
+    }
+
+    fn naive_play(cards: &[Card]) -> (u64, u64) {
+        let mut sum = 0;
+        let mut scratchcard_count = 0;
+        let mut multipliers: Vec<u64> = Vec::new();
+        for card in cards {
+            let mut current_multiplier = 1;
+            if !multipliers.is_empty() {
+                current_multiplier += multipliers.remove(0);
+            }
+            scratchcard_count += current_multiplier;
+            for i in 0..card.matches as usize {
+                if i == multipliers.len() {
+                    multipliers.push(0);
+                }
+                multipliers[i] += current_multiplier;
+            }
+            sum += card.value;
+        }
+        (sum, scratchcard_count)
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_million_cards() {
+        use std::time::Instant;
+
+        // xorshift, so the deck is the same on every run.
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound) as u32
+        };
+        let card_count = 1_000_000;
+        let decks: Vec<(Vec<u32>, Vec<u32>)> = (0..card_count)
+            .map(|_| {
+                let winners = (0..10).map(|_| next(100)).collect();
+                let mine = (0..25).map(|_| next(100)).collect();
+                (winners, mine)
+            })
+            .collect();
+
+        let start = Instant::now();
+        let matches: Vec<u32> = decks
+            .iter()
+            .map(|(winners, mine)| count_matches(winners, mine))
+            .collect();
+        let elapsed = start.elapsed();
+        let start = Instant::now();
+        let naive_matches: Vec<u32> = decks
+            .iter()
+            .map(|(winners, mine)| naive_count_matches(winners, mine))
+            .collect();
+        let naive_elapsed = start.elapsed();
+        assert_eq!(naive_matches, matches);
+        println!(
+            "{} cards, matching: set {:?}, vec {:?}",
+            card_count, elapsed, naive_elapsed
+        );
+
+        // Random decks win about 2.4 copies per card, so their counts grow
+        // exponentially and overflow. Instead every 5000th card wins a copy
+        // of each card up to the next one, keeping thousands of counts
+        // queued, and a few cards in between win one to three.
+        let cards: Vec<Card> = (1..=card_count as u32)
+            .map(|id| {
+                let matches = if id % 5000 == 1 {
+                    4999
+                } else if next(10) == 0 {
+                    next(4)
+                } else {
+                    0
+                };
+                Card {
+                    id,
+                    value: 0,
+                    matches,
+                }
+            })
+            .collect();
+        let start = Instant::now();
+        let totals = play(cards.iter()).unwrap();
+        let elapsed = start.elapsed();
+        let start = Instant::now();
+        let naive_totals = naive_play(&cards);
+        let naive_elapsed = start.elapsed();
+        assert_eq!(naive_totals, totals);
+        println!(
+            "{} cards, copies: VecDeque {:?}, Vec::remove(0) {:?}",
+            card_count, elapsed, naive_elapsed
+        );
+    }
+
+    #[test]
+    fn test_process_input_puzzle() {
+        let result = process_input(include_str!("../puzzle.input").as_bytes()).unwrap();
+        assert_eq!((20855, 5489600), (result.sum, result.scratchcard_count));
     }
 }